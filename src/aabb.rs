@@ -106,6 +106,30 @@ impl Aabb {
         true
     }
 
+    /// 使用预先求好的方向倒数做 slab 测试，供 BVH 遍历反复调用
+    pub fn hit_inv(&self, origin: &Point3, inv_dir: &Vec3, mut ray_t: Interval) -> bool {
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+
+            let t0 = (ax.min - origin[axis]) * inv_dir[axis];
+            let t1 = (ax.max - origin[axis]) * inv_dir[axis];
+
+            let (t_min, t_max) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            if t_min > ray_t.min {
+                ray_t.min = t_min;
+            }
+            if t_max < ray_t.max {
+                ray_t.max = t_max;
+            }
+
+            if ray_t.max <= ray_t.min {
+                return false;
+            }
+        }
+        true
+    }
+
     pub fn longest_axis(&self) -> usize {
         if self.x.size() > self.y.size() {
             if self.x.size() > self.z.size() {
@@ -122,6 +146,23 @@ impl Aabb {
         }
     }
 
+    /// 表面积，用于表面积启发式（SAH）
+    pub fn surface_area(&self) -> f64 {
+        let dx = self.x.size().max(0.0);
+        let dy = self.y.size().max(0.0);
+        let dz = self.z.size().max(0.0);
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    /// 包围盒中心
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max),
+        )
+    }
+
     fn pad_to_minimums(&mut self) {
         let delta = 0.0001;
         if self.x.size() < delta {
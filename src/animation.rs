@@ -0,0 +1,354 @@
+use crate::aabb::Aabb;
+use crate::camera::Camera;
+use crate::checkpoint::SceneHasher;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::rtweekend::{INFINITY, path_with_suffix};
+use crate::vec3::{Point3, Vec3};
+use std::ops::{Add, Mul, Sub};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// 关键帧之间的插值方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    /// 线性插值
+    Linear,
+    /// Catmull-Rom 样条，经过所有关键帧且速度连续
+    CatmullRom,
+}
+
+/// 可以做插值的值（标量或向量）
+pub trait Animatable:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f64, Output = Self>
+{
+}
+
+impl<T> Animatable for T where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T> {}
+
+/// 一条关键帧轨道，关键帧按时间排序
+#[derive(Debug, Clone)]
+pub struct Track<T> {
+    keys: Vec<(f64, T)>,
+    pub interpolation: Interpolation,
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self {
+            keys: Vec::new(),
+            interpolation,
+        }
+    }
+
+    /// 添加关键帧，同一时刻的关键帧会被替换
+    pub fn key(mut self, time: f64, value: T) -> Self {
+        match self.keys.binary_search_by(|(t, _)| t.total_cmp(&time)) {
+            Ok(index) => self.keys[index].1 = value,
+            Err(index) => self.keys.insert(index, (time, value)),
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// 各关键帧的时刻
+    fn key_times(&self) -> impl Iterator<Item = f64> + '_ {
+        self.keys.iter().map(|(t, _)| *t)
+    }
+
+    /// 关键帧覆盖的时间范围
+    pub fn time_range(&self) -> Option<Interval> {
+        let first = self.keys.first()?.0;
+        let last = self.keys.last()?.0;
+        Some(Interval::new(first, last))
+    }
+
+    /// 在 `time` 时刻求值；范围外（或时刻为 NaN）保持首尾关键帧的值，没有关键帧时返回 `None`
+    pub fn sample(&self, time: f64) -> Option<T> {
+        let (first_time, first) = *self.keys.first()?;
+        let (last_time, last) = *self.keys.last()?;
+        if time.is_nan() || time <= first_time {
+            return Some(first);
+        }
+        if time >= last_time {
+            return Some(last);
+        }
+
+        // keys[i] <= time < keys[i + 1]
+        let i = self.keys.partition_point(|(t, _)| *t <= time) - 1;
+        let (t1, p1) = self.keys[i];
+        let (t2, p2) = self.keys[i + 1];
+        let s = (time - t1) / (t2 - t1);
+
+        Some(match self.interpolation {
+            Interpolation::Linear => p1 + (p2 - p1) * s,
+            Interpolation::CatmullRom => {
+                // 端点处复制首尾关键帧
+                let p0 = if i > 0 { self.keys[i - 1].1 } else { p1 };
+                let p3 = if i + 2 < self.keys.len() {
+                    self.keys[i + 2].1
+                } else {
+                    p2
+                };
+                catmull_rom(p0, p1, p2, p3, s)
+            }
+        })
+    }
+}
+
+/// 均匀 Catmull-Rom 样条在 p1、p2 之间的插值
+fn catmull_rom<T: Animatable>(p0: T, p1: T, p2: T, p3: T, s: f64) -> T {
+    let s2 = s * s;
+    let s3 = s2 * s;
+    (p1 * 2.0
+        + (p2 - p0) * s
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * s2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * s3)
+        * 0.5
+}
+
+/// 相机参数的关键帧动画，空轨道不改变对应参数
+#[derive(Debug, Clone)]
+pub struct CameraAnimation {
+    pub lookfrom: Track<Point3>,
+    pub lookat: Track<Point3>,
+    pub vfov: Track<f64>,
+    pub focus_dist: Track<f64>,
+}
+
+impl CameraAnimation {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self {
+            lookfrom: Track::new(interpolation),
+            lookat: Track::new(interpolation),
+            vfov: Track::new(interpolation),
+            focus_dist: Track::new(interpolation),
+        }
+    }
+
+    /// 把 `time` 时刻的参数写入相机
+    pub fn apply(&self, camera: &mut Camera, time: f64) {
+        if let Some(lookfrom) = self.lookfrom.sample(time) {
+            camera.lookfrom = lookfrom;
+        }
+        if let Some(lookat) = self.lookat.sample(time) {
+            camera.lookat = lookat;
+        }
+        if let Some(vfov) = self.vfov.sample(time) {
+            camera.vfov = vfov;
+        }
+        if let Some(focus_dist) = self.focus_dist.sample(time) {
+            camera.focus_dist = focus_dist;
+        }
+    }
+}
+
+/// 随时间变化的变换：先均匀缩放，再绕 y 轴旋转（度），最后平移
+///
+/// 按射线时间求变换，因此快门开启期间的运动会产生运动模糊。缩放关键帧不能为 0，
+/// 插值经过 0 的时刻物体不可见
+#[derive(Debug)]
+pub struct AnimatedTransform {
+    object: Arc<dyn Hittable + Send + Sync>,
+    translation: Track<Vec3>,
+    rotation_y: Track<f64>,
+    scale: Track<f64>,
+    bbox: Aabb,
+}
+
+impl AnimatedTransform {
+    pub fn new(
+        object: Arc<dyn Hittable + Send + Sync>,
+        translation: Track<Vec3>,
+        rotation_y: Track<f64>,
+        scale: Track<f64>,
+    ) -> Self {
+        assert!(
+            scale.keys.iter().all(|(_, s)| *s != 0.0),
+            "缩放关键帧不能为 0"
+        );
+        let mut transform = Self {
+            object,
+            translation,
+            rotation_y,
+            scale,
+            bbox: Aabb::EMPTY,
+        };
+        transform.bbox = transform.compute_bbox();
+        transform
+    }
+
+    /// 只做平移动画
+    pub fn translating(object: Arc<dyn Hittable + Send + Sync>, translation: Track<Vec3>) -> Self {
+        Self::new(
+            object,
+            translation,
+            Track::new(Interpolation::Linear),
+            Track::new(Interpolation::Linear),
+        )
+    }
+
+    /// `time` 时刻的 (平移, sin θ, cos θ, 缩放)
+    fn transform_at(&self, time: f64) -> (Vec3, f64, f64, f64) {
+        let offset = self.translation.sample(time).unwrap_or_default();
+        let radians = self.rotation_y.sample(time).unwrap_or(0.0).to_radians();
+        let scale = self.scale.sample(time).unwrap_or(1.0);
+        (offset, radians.sin(), radians.cos(), scale)
+    }
+
+    /// 在关键帧覆盖的时间范围内均匀取若干时刻变换物体包围盒的 8 个角点，取并集
+    ///
+    /// 样条可能越过关键帧，因此在关键帧之间也取样，并留出少量余量
+    fn compute_bbox(&self) -> Aabb {
+        const STEPS: usize = 64;
+
+        let mut times = Vec::new();
+        for track_range in [
+            self.translation.time_range(),
+            self.rotation_y.time_range(),
+            self.scale.time_range(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            times.extend(sample_times(track_range, STEPS));
+        }
+        if times.is_empty() {
+            times.push(0.0);
+        }
+
+        self.bbox_at_times(&self.object.bounding_box(), times)
+    }
+
+    /// 物体包围盒 `bbox` 在 `times` 各时刻变换后的并集，外扩 1% 的对角线长度
+    fn bbox_at_times(&self, bbox: &Aabb, times: impl IntoIterator<Item = f64>) -> Aabb {
+        let mut min = Point3::new(INFINITY, INFINITY, INFINITY);
+        let mut max = Point3::new(-INFINITY, -INFINITY, -INFINITY);
+
+        for time in times {
+            let (offset, sin_theta, cos_theta, scale) = self.transform_at(time);
+            for i in 0..2 {
+                for j in 0..2 {
+                    for k in 0..2 {
+                        let x = if i == 0 { bbox.x.min } else { bbox.x.max } * scale;
+                        let y = if j == 0 { bbox.y.min } else { bbox.y.max } * scale;
+                        let z = if k == 0 { bbox.z.min } else { bbox.z.max } * scale;
+
+                        let tester = Vec3::new(
+                            cos_theta * x + sin_theta * z,
+                            y,
+                            -sin_theta * x + cos_theta * z,
+                        ) + offset;
+
+                        for c in 0..3 {
+                            min[c] = min[c].min(tester[c]);
+                            max[c] = max[c].max(tester[c]);
+                        }
+                    }
+                }
+            }
+        }
+
+        let padding = 0.01 * (max - min).length();
+        let padding = Vec3::new(padding, padding, padding);
+        Aabb::from_points(min - padding, max + padding)
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let (offset, sin_theta, cos_theta, scale) = self.transform_at(r.time());
+        if scale == 0.0 {
+            return false;
+        }
+
+        // 世界空间 -> 物体空间（仿射变换不改变射线参数 t）
+        let o = *r.origin() - offset;
+        let d = *r.direction();
+        let origin = Point3::new(
+            cos_theta * o.x() - sin_theta * o.z(),
+            o.y(),
+            sin_theta * o.x() + cos_theta * o.z(),
+        ) / scale;
+        let direction = Vec3::new(
+            cos_theta * d.x() - sin_theta * d.z(),
+            d.y(),
+            sin_theta * d.x() + cos_theta * d.z(),
+        ) / scale;
+
+        let local_r = Ray::with_origin_dir_time(origin, direction, r.time());
+        if !self.object.hit(&local_r, ray_t, rec) {
+            return false;
+        }
+
+        // 物体空间 -> 世界空间，均匀缩放不改变法向方向
+        let rotate = |v: &Vec3| {
+            Vec3::new(
+                cos_theta * v.x() + sin_theta * v.z(),
+                v.y(),
+                -sin_theta * v.x() + cos_theta * v.z(),
+            )
+        };
+        rec.p = rotate(&(rec.p * scale)) + offset;
+        rec.normal = rotate(&rec.normal);
+        rec.geometric_normal = rotate(&rec.geometric_normal);
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("animated_transform");
+        self.object.hash_content(hasher);
+        hasher.write_debug(&self.translation);
+        hasher.write_debug(&self.rotation_y);
+        hasher.write_debug(&self.scale);
+    }
+
+    /// 只在 `time` 内取样，一帧之内的运动很小，取样也可以少一些
+    fn bounding_box_during(&self, time: Interval) -> Aabb {
+        const STEPS: usize = 8;
+
+        let bbox = self.object.bounding_box_during(time);
+        let keys = self
+            .translation
+            .key_times()
+            .chain(self.rotation_y.key_times())
+            .chain(self.scale.key_times())
+            .filter(|t| time.contains(*t));
+        let times = sample_times(time, STEPS).chain(keys);
+        self.bbox_at_times(&bbox, times)
+    }
+}
+
+/// `range` 内包括两端在内均匀分布的 `steps + 1` 个时刻
+fn sample_times(range: Interval, steps: usize) -> impl Iterator<Item = f64> {
+    (0..=steps).map(move |k| range.min + k as f64 / steps as f64 * range.size())
+}
+
+/// 由输出路径模板得到第 `frame` 帧的文件路径
+///
+/// 模板中连续的 `#` 会被替换为补零的帧号，如 `frames/shot_####.ppm` -> `frames/shot_0012.ppm`；
+/// 没有 `#` 时在扩展名前追加 `_帧号`
+pub fn frame_path(pattern: &str, frame: i32) -> PathBuf {
+    if let Some(start) = pattern.find('#') {
+        let width = pattern[start..].chars().take_while(|&c| c == '#').count();
+        let end = start + width;
+        return PathBuf::from(format!(
+            "{}{:0width$}{}",
+            &pattern[..start],
+            frame,
+            &pattern[end..],
+            width = width
+        ));
+    }
+
+    path_with_suffix(Path::new(pattern), &frame.to_string())
+}
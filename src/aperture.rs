@@ -0,0 +1,86 @@
+use crate::rtw_stb_image::RtwImage;
+use crate::rtweekend::{PI, degrees_to_radians, random_double, random_double_range};
+use crate::vec3::random_in_unit_disk;
+use std::sync::Arc;
+
+/// 光圈形状，决定离焦高光（散景）的形状
+#[derive(Debug, Clone)]
+pub enum Aperture {
+    /// 圆形光圈
+    Circular,
+    /// 由 `blades` 片叶片构成的正多边形光圈，`rotation` 为旋转角度（度）
+    Polygon { blades: u32, rotation: f64 },
+    /// 以图像亮度作为透光率的光圈遮罩，图像铺满光圈的外接正方形
+    Mask(Arc<RtwImage>),
+}
+
+impl Aperture {
+    /// 多边形光圈
+    pub fn polygon(blades: u32, rotation: f64) -> Self {
+        Aperture::Polygon {
+            blades: blades.max(3),
+            rotation,
+        }
+    }
+
+    /// 从图像文件加载光圈遮罩
+    pub fn mask(filename: &str) -> Self {
+        Aperture::Mask(Arc::new(RtwImage::new(filename)))
+    }
+
+    /// 在光圈上采样一点，坐标位于单位圆内
+    pub fn sample(&self) -> (f64, f64) {
+        match self {
+            Aperture::Circular => {
+                let p = random_in_unit_disk();
+                (p.x(), p.y())
+            }
+            Aperture::Polygon { blades, rotation } => {
+                Self::sample_polygon(*blades, degrees_to_radians(*rotation))
+            }
+            Aperture::Mask(image) => Self::sample_mask(image),
+        }
+    }
+
+    /// 均匀采样内接于单位圆的正多边形：先等概率选一个三角扇区，再在三角形内均匀采样
+    fn sample_polygon(blades: u32, rotation: f64) -> (f64, f64) {
+        let sector = ((random_double() * blades as f64) as u32).min(blades - 1);
+        let step = 2.0 * PI / blades as f64;
+        let a0 = rotation + sector as f64 * step;
+        let a1 = a0 + step;
+
+        let mut s = random_double();
+        let mut t = random_double();
+        if s + t > 1.0 {
+            s = 1.0 - s;
+            t = 1.0 - t;
+        }
+
+        (s * a0.cos() + t * a1.cos(), s * a0.sin() + t * a1.sin())
+    }
+
+    /// 按遮罩亮度做拒绝采样；遮罩几乎全黑时退回光圈中心
+    fn sample_mask(image: &RtwImage) -> (f64, f64) {
+        if image.width() == 0 || image.height() == 0 {
+            let p = random_in_unit_disk();
+            return (p.x(), p.y());
+        }
+
+        for _ in 0..256 {
+            let x = random_double_range(-1.0, 1.0);
+            let y = random_double_range(-1.0, 1.0);
+
+            let i = ((x + 1.0) / 2.0 * image.width() as f64) as u32;
+            let j = ((1.0 - y) / 2.0 * image.height() as f64) as u32;
+            let pixel = image.pixel_data(i, j);
+            let transmittance =
+                (pixel[0] as f64 + pixel[1] as f64 + pixel[2] as f64) / (3.0 * 255.0);
+
+            if random_double() < transmittance {
+                return (x, y);
+            }
+        }
+
+        (0.0, 0.0)
+    }
+}
@@ -1,20 +1,362 @@
 use rayon::join;
-use std::{cmp::Ordering, sync::Arc};
+use rayon::prelude::*;
+use std::{fmt, sync::Arc, time::Duration, time::Instant};
 
-use crate::{aabb::Aabb, hittable::Hittable, hittable_list::HittableList, interval::Interval};
+use crate::{
+    aabb::Aabb,
+    checkpoint::SceneHasher,
+    flat_bvh::{FlatBvh, FlatNode, build_nodes},
+    hittable::Hittable,
+    hittable_list::HittableList,
+    interval::Interval,
+    packet::{PacketHits, RayPacket},
+    stats::count_node_visit,
+    wide_bvh::WideBvh,
+};
+
+/// 估算 SAH 代价时一次包围盒测试与一次图元求交的相对开销
+pub(crate) const TRAVERSAL_COST: f64 = 1.0;
+pub(crate) const INTERSECT_COST: f64 = 1.0;
+
+/// 默认的重建阈值
+const DEFAULT_REBUILD_THRESHOLD: f64 = 1.5;
+
+/// BVH 的划分方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitMethod {
+    /// 沿最长轴排序后从中间分开
+    Median,
+    /// 分桶的表面积启发式：把图元中心分到 `bins` 个桶中，选择代价最小的分界
+    Sah { bins: usize },
+    /// 按图元中心的 Morton 编码排序后自顶向下划分（LBVH），再做 `treelet_rounds` 轮 treelet 重组
+    Lbvh { treelet_rounds: usize },
+}
+
+/// BVH 构建参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BvhOptions {
+    pub split: SplitMethod,
+    pub max_leaf_size: usize, // 叶节点最多包含的图元数
+    /// 重拟合后 SAH 代价超过基准的多少倍就完全重建（见 `FlatBvh::refit`）
+    pub rebuild_threshold: f64,
+}
+
+impl BvhOptions {
+    /// 中值划分，每个叶节点一个图元
+    pub fn median() -> Self {
+        Self {
+            split: SplitMethod::Median,
+            max_leaf_size: 1,
+            rebuild_threshold: DEFAULT_REBUILD_THRESHOLD,
+        }
+    }
+
+    /// 分桶 SAH
+    pub fn sah(bins: usize, max_leaf_size: usize) -> Self {
+        Self {
+            split: SplitMethod::Sah { bins: bins.max(2) },
+            max_leaf_size: max_leaf_size.max(1),
+            rebuild_threshold: DEFAULT_REBUILD_THRESHOLD,
+        }
+    }
+
+    /// LBVH，`treelet_rounds` 为 0 时不做 treelet 重组
+    pub fn lbvh(treelet_rounds: usize, max_leaf_size: usize) -> Self {
+        Self {
+            split: SplitMethod::Lbvh { treelet_rounds },
+            max_leaf_size: max_leaf_size.max(1),
+            rebuild_threshold: DEFAULT_REBUILD_THRESHOLD,
+        }
+    }
+}
+
+impl Default for BvhOptions {
+    /// 与原来的 `BvhNode::new` 相同的中值划分，SAH 需要显式选择
+    fn default() -> Self {
+        Self::median()
+    }
+}
+
+/// BVH 的存储方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BvhLayout {
+    /// `BvhNode` 组成的二叉树
+    #[default]
+    Tree,
+    /// 线性化的二叉树（`FlatBvh`）
+    Flat,
+    /// 由二叉树折叠得到的四叉树，SIMD 测试包围盒（`WideBvh`）
+    Wide,
+}
+
+/// 按给定布局为物体列表构建 BVH
+pub fn build_bvh(
+    list: &HittableList,
+    options: BvhOptions,
+    layout: BvhLayout,
+) -> Arc<dyn Hittable + Send + Sync> {
+    match layout {
+        BvhLayout::Tree => BvhNode::with_options(list, options),
+        BvhLayout::Flat => Arc::new(FlatBvh::new(list, options)),
+        BvhLayout::Wide => Arc::new(WideBvh::new(list, options)),
+    }
+}
+
+/// 构建时统计的树的信息
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BvhStats {
+    pub interior_nodes: usize,
+    pub leaves: usize,
+    pub primitives: usize,
+    pub max_depth: usize,
+    pub sah_cost: f64, // 按表面积加权的期望遍历代价
+    pub build_time: Duration,
+}
+
+impl BvhStats {
+    pub(crate) fn leaf(count: usize) -> Self {
+        Self {
+            interior_nodes: 0,
+            leaves: 1,
+            primitives: count,
+            max_depth: 0,
+            sah_cost: INTERSECT_COST * count as f64,
+            build_time: Duration::ZERO,
+        }
+    }
+
+    /// 左右子节点是同一个 `child` 的内部节点（只有一个叶节点的树的根），子节点只计一次
+    pub(crate) fn shared_child(child: &Self) -> Self {
+        Self {
+            interior_nodes: child.interior_nodes + 1,
+            leaves: child.leaves,
+            primitives: child.primitives,
+            max_depth: child.max_depth + 1,
+            sah_cost: TRAVERSAL_COST + child.sah_cost,
+            build_time: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn interior(bbox: &Aabb, left: (&Aabb, &Self), right: (&Aabb, &Self)) -> Self {
+        let area = bbox.surface_area();
+        let (left_box, left) = left;
+        let (right_box, right) = right;
+        let sah_cost = if area > 0.0 {
+            TRAVERSAL_COST
+                + (left_box.surface_area() * left.sah_cost
+                    + right_box.surface_area() * right.sah_cost)
+                    / area
+        } else {
+            TRAVERSAL_COST + left.sah_cost + right.sah_cost
+        };
+
+        Self {
+            interior_nodes: left.interior_nodes + right.interior_nodes + 1,
+            leaves: left.leaves + right.leaves,
+            primitives: left.primitives + right.primitives,
+            max_depth: left.max_depth.max(right.max_depth) + 1,
+            sah_cost,
+            build_time: Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} interior nodes, {} leaves, {} primitives, depth {}, SAH cost {:.2}, built in {:.2} ms",
+            self.interior_nodes,
+            self.leaves,
+            self.primitives,
+            self.max_depth,
+            self.sah_cost,
+            self.build_time.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// `BvhNode` 的子节点，重拟合时据此区分内部节点与叶节点中的图元
+#[derive(Debug, Clone)]
+enum BvhChild {
+    Node(Arc<BvhNode>),
+    /// 只有一个图元的叶节点
+    Object(Arc<dyn Hittable + Send + Sync>),
+    /// 含多个图元的叶节点
+    List(Arc<HittableList>),
+}
+
+impl BvhChild {
+    fn get(&self) -> &(dyn Hittable + Send + Sync) {
+        match self {
+            BvhChild::Node(node) => node.as_ref(),
+            BvhChild::Object(object) => object.as_ref(),
+            BvhChild::List(list) => list.as_ref(),
+        }
+    }
+
+    fn stats(&self) -> BvhStats {
+        match self {
+            BvhChild::Node(node) => node.stats,
+            BvhChild::Object(_) => BvhStats::leaf(1),
+            BvhChild::List(list) => BvhStats::leaf(list.objects.len()),
+        }
+    }
+
+    /// 是否与 `other` 指向同一个子节点（只有一个叶节点的树的根）
+    fn is_same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BvhChild::Node(a), BvhChild::Node(b)) => Arc::ptr_eq(a, b),
+            (BvhChild::Object(a), BvhChild::Object(b)) => Arc::ptr_eq(a, b),
+            (BvhChild::List(a), BvhChild::List(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// 重拟合子树，图元自身支持重拟合时一并更新
+    fn refitted(&self, time: Interval) -> Self {
+        match self {
+            BvhChild::Node(node) => BvhChild::Node(node.refit_tree(time)),
+            BvhChild::Object(object) => {
+                BvhChild::Object(object.refitted(time).unwrap_or_else(|| object.clone()))
+            }
+            BvhChild::List(list) => {
+                let mut refitted = HittableList::new();
+                for object in &list.objects {
+                    refitted.add(object.refitted(time).unwrap_or_else(|| object.clone()));
+                }
+                BvhChild::List(Arc::new(refitted))
+            }
+        }
+    }
+
+    /// 按叶节点顺序收集子树中的图元
+    fn collect_objects(&self, objects: &mut Vec<Arc<dyn Hittable + Send + Sync>>) {
+        match self {
+            BvhChild::Node(node) => node.collect_objects(objects),
+            BvhChild::Object(object) => objects.push(object.clone()),
+            BvhChild::List(list) => objects.extend(list.objects.iter().cloned()),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct BvhNode {
-    left: Arc<dyn Hittable + Send + Sync>,
-    right: Arc<dyn Hittable + Send + Sync>,
+    left: BvhChild,
+    right: BvhChild,
     bbox: Aabb,
+    stats: BvhStats,
+    options: BvhOptions,
+    /// 衡量重拟合后树质量的基准 SAH 代价，含义与 `FlatBvh` 相同，只有根节点使用
+    build_cost: Option<f64>,
 }
 
 impl BvhNode {
+    /// 中值划分构建，SAH 等其他方式使用 `with_options`
     pub fn new(list: &HittableList) -> Arc<Self> {
-        let mut objects = list.objects.clone();
-        let len = objects.len();
-        Self::from_objects(&mut objects, 0, len)
+        Self::with_options(list, BvhOptions::median())
+    }
+
+    /// 划分方式与 `FlatBvh` 相同（见 `flat_bvh::build_nodes`），再把线性化的节点转换为树
+    pub fn with_options(list: &HittableList, options: BvhOptions) -> Arc<Self> {
+        let start = Instant::now();
+        let boxes: Vec<Aabb> = list.objects.iter().map(|o| o.bounding_box()).collect();
+        let mut node = Self::build(&list.objects, &boxes, &options);
+        // 刚构建完，只有这里持有引用
+        Arc::get_mut(&mut node).unwrap().stats.build_time = start.elapsed();
+        node
+    }
+
+    /// 按包围盒 `boxes` 划分 `objects`，得到线性化的节点后转换为 `BvhNode` 树
+    fn build(
+        objects: &[Arc<dyn Hittable + Send + Sync>],
+        boxes: &[Aabb],
+        options: &BvhOptions,
+    ) -> Arc<Self> {
+        let (nodes, order, _) = build_nodes(boxes, options);
+        let objects: Vec<_> = order.iter().map(|&i| objects[i].clone()).collect();
+        Self::from_nodes(&nodes, &objects, *options)
+    }
+
+    /// 把线性化的节点转换为 `BvhNode` 树，`objects` 已按叶节点顺序排列
+    ///
+    /// `options` 用于重拟合后的重建。没有节点时返回空树
+    pub fn from_nodes(
+        nodes: &[FlatNode],
+        objects: &[Arc<dyn Hittable + Send + Sync>],
+        options: BvhOptions,
+    ) -> Arc<Self> {
+        let Some(root) = nodes.first() else {
+            return Self::empty(options);
+        };
+        if !root.is_leaf() {
+            return Self::from_flat_interior(nodes, objects, 0, &options);
+        }
+
+        // 所有图元都在根节点中时左右子节点相同
+        let child = Self::from_flat(nodes, objects, 0, &options);
+        let stats = BvhStats::shared_child(&child.stats());
+        let bbox = root.bbox;
+        Arc::new(Self {
+            left: child.clone(),
+            right: child,
+            bbox,
+            stats,
+            options,
+            build_cost: None,
+        })
+    }
+
+    /// 把以 `nodes[index]` 为根的线性化子树转换为子节点，叶节点转换为物体本身或物体列表
+    fn from_flat(
+        nodes: &[FlatNode],
+        objects: &[Arc<dyn Hittable + Send + Sync>],
+        index: usize,
+        options: &BvhOptions,
+    ) -> BvhChild {
+        let node = &nodes[index];
+        if !node.is_leaf() {
+            return BvhChild::Node(Self::from_flat_interior(nodes, objects, index, options));
+        }
+
+        let start = node.offset as usize;
+        let count = node.count as usize;
+        if count == 1 {
+            return BvhChild::Object(objects[start].clone());
+        }
+        let mut leaf = HittableList::new();
+        for object in &objects[start..start + count] {
+            leaf.add(object.clone());
+        }
+        BvhChild::List(Arc::new(leaf))
+    }
+
+    fn from_flat_interior(
+        nodes: &[FlatNode],
+        objects: &[Arc<dyn Hittable + Send + Sync>],
+        index: usize,
+        options: &BvhOptions,
+    ) -> Arc<Self> {
+        let right_index = nodes[index].offset as usize;
+        let (left, right) = join(
+            || Self::from_flat(nodes, objects, index + 1, options),
+            || Self::from_flat(nodes, objects, right_index, options),
+        );
+
+        let bbox = nodes[index].bbox;
+        let stats = BvhStats::interior(
+            &bbox,
+            (&nodes[index + 1].bbox, &left.stats()),
+            (&nodes[right_index].bbox, &right.stats()),
+        );
+        Arc::new(Self {
+            left,
+            right,
+            bbox,
+            stats,
+            options: *options,
+            build_cost: None,
+        })
     }
 
     pub fn from_objects(
@@ -22,96 +364,110 @@ impl BvhNode {
         start: usize,
         end: usize,
     ) -> Arc<Self> {
-        let object_span = end - start;
-
-        let mut bbox = Aabb::EMPTY;
-        for i in start..end {
-            bbox = Aabb::from_aabbs(bbox, objects[i].bounding_box());
-        }
+        let objects = &objects[start..end];
+        let boxes: Vec<Aabb> = objects.iter().map(|o| o.bounding_box()).collect();
+        Self::build(objects, &boxes, &BvhOptions::median())
+    }
 
-        let axis = bbox.longest_axis();
+    /// 当前树的统计信息，重拟合后 `sah_cost` 随之更新
+    pub fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
 
-        let comparator = match axis {
-            0 => Self::box_x_compare,
-            1 => Self::box_y_compare,
-            _ => Self::box_z_compare,
-        };
+    /// 树的退化程度：当前 SAH 代价与基准之比，刚构建完或刚记录基准时为 1
+    pub fn degradation(&self) -> f64 {
+        match self.build_cost {
+            Some(cost) if cost > 0.0 => self.stats.sah_cost / cost,
+            _ => 1.0,
+        }
+    }
 
-        let (left, right) = match object_span {
-            1 => {
-                let object = objects[start].clone();
-                (object.clone(), object)
-            }
-            2 => {
-                let first = objects[start].clone();
-                let second = objects[start + 1].clone();
-                (first, second)
-            }
-            _ => {
-                // 对当前区间的对象进行排序
-                objects[start..end].sort_by(|a, b| comparator(a, b));
-                let mid = start + object_span / 2;
-
-                // 将当前区间的对象分成两个独立的部分
-                let mut left_objects = objects[start..mid].to_vec();
-                let mut right_objects = objects[mid..end].to_vec();
-
-                // 提前计算长度
-                let left_len = left_objects.len();
-                let right_len = right_objects.len();
-
-                // 使用 rayon::join 并行构建左右子树
-                let (left_node, right_node) = join(
-                    || Self::from_objects(&mut left_objects, 0, left_len),
-                    || Self::from_objects(&mut right_objects, 0, right_len),
-                );
-
-                (
-                    left_node as Arc<dyn Hittable + Send + Sync>,
-                    right_node as Arc<dyn Hittable + Send + Sync>,
-                )
-            }
-        };
+    /// 按图元在 `time` 内的包围盒自底向上更新各节点，返回拓扑不变的新树
+    ///
+    /// 与 `FlatBvh::refit` 相同：构建后的第一次重拟合只记录基准代价，之后退化程度超过
+    /// `BvhOptions::rebuild_threshold` 时按新包围盒完全重建
+    pub fn refit(&self, time: Interval) -> Arc<Self> {
+        if self.stats.primitives == 0 {
+            return Self::empty(self.options);
+        }
+        let mut node = self.refit_tree(time);
+        // 刚生成，只有这里持有引用
+        let root = Arc::get_mut(&mut node).unwrap();
+        root.stats.build_time = self.stats.build_time;
+        root.build_cost = Some(self.build_cost.unwrap_or(root.stats.sah_cost));
 
-        Arc::new(Self { left, right, bbox })
+        if self.build_cost.is_some() && node.degradation() > self.options.rebuild_threshold {
+            return node.rebuild(time);
+        }
+        node
     }
 
-    fn box_compare(
-        a: &Arc<dyn Hittable + Send + Sync>,
-        b: &Arc<dyn Hittable + Send + Sync>,
-        axis_index: usize,
-    ) -> Ordering {
-        let a_bbox = a.bounding_box();
-        let b_bbox = b.bounding_box();
+    /// 按图元在 `time` 内的包围盒完全重建
+    pub fn rebuild(&self, time: Interval) -> Arc<Self> {
+        let start = Instant::now();
+        let mut objects = Vec::with_capacity(self.stats.primitives);
+        self.collect_objects(&mut objects);
+        let boxes: Vec<Aabb> = objects
+            .par_iter()
+            .map(|object| object.bounding_box_during(time))
+            .collect();
+        let mut node = Self::build(&objects, &boxes, &self.options);
+        let root = Arc::get_mut(&mut node).unwrap();
+        root.stats.build_time = start.elapsed();
+        root.build_cost = Some(root.stats.sah_cost);
+        node
+    }
 
-        let a_axis_interval = a_bbox.axis_interval(axis_index);
-        let b_axis_interval = b_bbox.axis_interval(axis_index);
+    /// 重拟合以本节点为根的子树，不检查退化程度
+    fn refit_tree(&self, time: Interval) -> Arc<Self> {
+        let (left, right) = if self.left.is_same(&self.right) {
+            let child = self.left.refitted(time);
+            (child.clone(), child)
+        } else {
+            join(|| self.left.refitted(time), || self.right.refitted(time))
+        };
 
-        a_axis_interval
-            .min
-            .partial_cmp(&b_axis_interval.min)
-            .unwrap()
-    }
+        let left_box = left.get().bounding_box_during(time);
+        let right_box = right.get().bounding_box_during(time);
+        let bbox = Aabb::from_aabbs(left_box, right_box);
+        let stats = if left.is_same(&right) {
+            BvhStats::shared_child(&left.stats())
+        } else {
+            BvhStats::interior(
+                &bbox,
+                (&left_box, &left.stats()),
+                (&right_box, &right.stats()),
+            )
+        };
 
-    fn box_x_compare(
-        a: &Arc<dyn Hittable + Send + Sync>,
-        b: &Arc<dyn Hittable + Send + Sync>,
-    ) -> Ordering {
-        Self::box_compare(a, b, 0)
+        Arc::new(Self {
+            left,
+            right,
+            bbox,
+            stats,
+            options: self.options,
+            build_cost: None,
+        })
     }
 
-    fn box_y_compare(
-        a: &Arc<dyn Hittable + Send + Sync>,
-        b: &Arc<dyn Hittable + Send + Sync>,
-    ) -> Ordering {
-        Self::box_compare(a, b, 1)
+    fn collect_objects(&self, objects: &mut Vec<Arc<dyn Hittable + Send + Sync>>) {
+        self.left.collect_objects(objects);
+        if !self.left.is_same(&self.right) {
+            self.right.collect_objects(objects);
+        }
     }
 
-    fn box_z_compare(
-        a: &Arc<dyn Hittable + Send + Sync>,
-        b: &Arc<dyn Hittable + Send + Sync>,
-    ) -> Ordering {
-        Self::box_compare(a, b, 2)
+    /// 不含图元的树，左右子节点都是空列表，永远不会被击中
+    fn empty(options: BvhOptions) -> Arc<Self> {
+        let child = BvhChild::List(Arc::new(HittableList::new()));
+        Arc::new(Self {
+            left: child.clone(),
+            right: child,
+            bbox: Aabb::EMPTY,
+            stats: BvhStats::default(),
+            options,
+            build_cost: None,
+        })
     }
 }
 
@@ -122,11 +478,12 @@ impl Hittable for BvhNode {
         ray_t: crate::interval::Interval,
         rec: &mut crate::hittable::HitRecord,
     ) -> bool {
+        count_node_visit();
         if !self.bbox.hit(r, ray_t) {
             return false;
         }
 
-        let hit_left = self.left.hit(r, ray_t, rec);
+        let hit_left = self.left.get().hit(r, ray_t, rec);
 
         let new_ray_t = if hit_left {
             Interval::new(ray_t.min, rec.t)
@@ -134,7 +491,7 @@ impl Hittable for BvhNode {
             ray_t
         };
 
-        let hit_right = self.right.hit(r, new_ray_t, rec);
+        let hit_right = self.right.get().hit(r, new_ray_t, rec);
 
         hit_left || hit_right
     }
@@ -142,6 +499,33 @@ impl Hittable for BvhNode {
     fn bounding_box(&self) -> Aabb {
         self.bbox
     }
+
+    /// 按叶节点顺序哈希图元，树的结构与构建统计不影响渲染结果
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        self.left.get().hash_content(hasher);
+        if !self.left.is_same(&self.right) {
+            self.right.get().hash_content(hasher);
+        }
+    }
+
+    fn hit_packet(&self, packet: &RayPacket, active: u64, hits: &mut PacketHits) {
+        count_node_visit();
+        let active = packet.hit_box(&self.bbox, active, hits);
+        if active == 0 {
+            return;
+        }
+
+        self.left.get().hit_packet(packet, active, hits);
+        self.right.get().hit_packet(packet, active, hits);
+    }
+
+    fn refitted(&self, time: Interval) -> Option<Arc<dyn Hittable + Send + Sync>> {
+        Some(self.refit(time))
+    }
+
+    fn bvh_stats(&self) -> Option<BvhStats> {
+        Some(self.stats)
+    }
 }
 
 unsafe impl Send for BvhNode {}
@@ -1,16 +1,45 @@
-use crate::color::{Color, write_color_to_string};
-use crate::hittable::Hittable;
+use crate::animation::{CameraAnimation, frame_path};
+use crate::aperture::Aperture;
+use crate::checkpoint::{Checkpoint, CheckpointError, Fnv64, SceneHasher};
+use crate::color::Color;
+use crate::debug_view::{DebugView, apply_heatmap, material_color};
+use crate::exposure::Exposure;
+use crate::film::Film;
+use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
+use crate::lens_system::LensSystem;
 use crate::material::ScatterRecord;
+use crate::packet::{MAX_PACKET_SIZE, PacketHits, RayPacket};
 use crate::pdf::{CosinePdf, HittablePdf, MixturePdf, Pdf};
+use crate::projection::Projection;
 use crate::ray::Ray;
-use crate::rtweekend::{INFINITY, degrees_to_radians, random_double};
-use crate::vec3::{Point3, Vec3, cross, random_in_unit_disk, unit_vector};
+use crate::rtweekend::{
+    INFINITY, degrees_to_radians, mix_seed, path_with_suffix, random_double, seed_thread_rng,
+};
+use crate::shutter::ShutterCurve;
+use crate::stats::{
+    RayCounters, RenderStats, count_primary_rays, count_secondary_ray, take_thread_counters,
+    thread_counters,
+};
+use crate::vec3::{Point3, Vec3, cross, dot, random_in_unit_disk, unit_vector};
 use rayon::prelude::*;
+use std::fs::{self, File};
 use std::io::{self, Write};
+use std::ops::Range;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// 自动对焦的目标
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FocusTarget {
+    /// 对焦到图像坐标 (i, j) 处像素中心看到的物体
+    Pixel(i32, i32),
+    /// 对焦到世界空间中的一点；视线被遮挡时对焦到遮挡物上
+    Point(Point3),
+}
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 /// 相机类，负责生成射线并渲染场景
 pub struct Camera {
     // 相机参数（公开）
@@ -26,11 +55,51 @@ pub struct Camera {
     pub defocus_angle: f64, // Variation angle of rays through each pixel
     pub focus_dist: f64,    // Distance from camera lookfrom point to plane of perfect focus
 
+    pub focus_target: Option<FocusTarget>, // 自动对焦目标，设置后 focus_dist 由场景求出
+
+    // 镜头与快门
+    pub projection: Projection,      // 投影方式（透视、正交、全景、鱼眼）
+    pub aperture: Aperture,          // 光圈形状（决定散景形状）
+    pub cat_eye: f64,                // 猫眼渐晕强度：画面角落处光瞳的偏移量（以光圈半径为单位）
+    pub exposure: Option<Exposure>,  // 物理曝光参数，None 时胶片响应为 1
+    pub shutter_open: f64,           // 快门开启时刻
+    pub shutter_close: f64,          // 快门关闭时刻
+    pub shutter_curve: ShutterCurve, // 快门透光曲线
+    pub seed: u64,                   // 采样随机数种子
+
+    // 真实镜头：设置后按镜头数据追踪射线，vfov、defocus_angle、光圈形状与移轴均不再生效
+    pub lens: Option<Arc<LensSystem>>,
+
+    // 移轴镜头
+    pub lens_shift_x: f64, // 水平移轴量（视口宽度的比例，向右为正）
+    pub lens_shift_y: f64, // 垂直移轴量（视口高度的比例，向上为正）
+    pub lens_tilt_x: f64,  // 焦平面绕水平轴的倾角（度），为正时画面上方的焦平面更远
+    pub lens_tilt_y: f64,  // 焦平面绕竖直轴的倾角（度），为正时画面右侧的焦平面更远
+
+    // 立体渲染时单只眼睛的参数（由 StereoCamera 设置）
+    pub eye_offset: f64,       // 眼睛沿相机右方向的偏移，0 为单目
+    pub convergence_dist: f64, // 两眼视线会聚的距离，INFINITY 表示平行
+
+    // 动画：render_sequence 按帧率求每帧时刻，快门角决定每帧的快门开启时长；
+    // 快门开启期间相机按射线时间取关键帧值，产生相机运动模糊
+    pub animation: Option<Arc<CameraAnimation>>,
+    pub frame_rate: f64,    // 每秒帧数
+    pub shutter_angle: f64, // 快门角（度），360 表示快门在整帧时间内开启
+
+    // 断点续渲：路径为 None 时不保存断点
+    pub checkpoint_path: Option<PathBuf>,
+    pub checkpoint_interval: i32, // 每完成多少轮采样保存一次
+
+    // 成束追踪：主射线按 packet_size × packet_size 的像素块一起求交，不大于 1 时逐条追踪
+    pub packet_size: i32,
+
+    // 调试视图：设置后输出伪彩色图像而不是辐射亮度，不做曝光缩放
+    pub debug_view: Option<DebugView>,
+
     // 私有成员
     image_height: i32,
-    pixel_samples_scale: f64, // 像素采样的缩放因子
-    sqrt_spp: i32,            // 样本数的平方根（分层采样时使用）
-    recip_sqrt_spp: f64,      //  1/sqrt_spp（分层采样时使用）
+    sqrt_spp: i32,       // 样本数的平方根（分层采样时使用）
+    recip_sqrt_spp: f64, //  1/sqrt_spp（分层采样时使用）
     center: Point3,
     pixel00_loc: Point3,
     pixel_delta_u: Vec3,
@@ -38,10 +107,17 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     w: Vec3,
-    defocus_disk_u: Vec3, // Defocus disk horizontal radius
-    defocus_disk_v: Vec3, // Defocus disk vertical radius
+    defocus_disk_u: Vec3,         // Defocus disk horizontal radius
+    defocus_disk_v: Vec3,         // Defocus disk vertical radius
+    shutter_interval: (f64, f64), // 射线时间的取值区间
+    focus_plane_normal: Vec3,     // 焦平面法向（倾角为 0 时等于 w）
+    lens_scale: f64,              // 真实镜头的亮度归一化系数（画面中心通光比例的倒数）
+    motion: Arc<Vec<Camera>>,     // 有相机动画时快门区间内均匀分布的各时刻的相机
 }
 
+/// 相机动画在一次快门内取样的时刻数，射线在相邻两个时刻之间随机选取
+const MOTION_STEPS: usize = 32;
+
 impl Camera {
     /// 创建新相机
     pub fn new() -> Self {
@@ -57,8 +133,30 @@ impl Camera {
             vup: Point3::new(0.0, 1.0, 0.0),
             defocus_angle: 0.0,
             focus_dist: 10.0,
+            focus_target: None,
+            aperture: Aperture::Circular,
+            cat_eye: 0.0,
+            projection: Projection::Perspective,
+            exposure: None,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            shutter_curve: ShutterCurve::Box,
+            seed: 0,
+            lens: None,
+            lens_shift_x: 0.0,
+            lens_shift_y: 0.0,
+            lens_tilt_x: 0.0,
+            lens_tilt_y: 0.0,
+            eye_offset: 0.0,
+            convergence_dist: INFINITY,
+            animation: None,
+            frame_rate: 24.0,
+            shutter_angle: 180.0,
+            checkpoint_path: None,
+            checkpoint_interval: 1,
+            packet_size: 0,
+            debug_view: None,
             image_height: 0,
-            pixel_samples_scale: 0.0,
             sqrt_spp: 0,
             recip_sqrt_spp: 0.0,
             center: Point3::default(),
@@ -70,95 +168,480 @@ impl Camera {
             w: Vec3::default(),
             defocus_disk_u: Vec3::default(),
             defocus_disk_v: Vec3::default(),
+            shutter_interval: (0.0, 1.0),
+            focus_plane_normal: Vec3::default(),
+            lens_scale: 1.0,
+            motion: Arc::new(Vec::new()),
         }
     }
 
-    /// 渲染给定场景
+    /// 渲染给定场景，图像写到标准输出，统计信息打印到标准错误并返回
     // pub fn render(&self, world: &impl Hittable, lights: &impl Hittable) {
     pub fn render(
         &self,
         world: Arc<dyn Hittable + Send + Sync>,
         lights: Arc<dyn Hittable + Send + Sync>,
-    ) {
+    ) -> Option<RenderStats> {
+        let (film, stats) = match self.render_film_with_stats(world, lights) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("\nERROR: {}", e);
+                return None;
+            }
+        };
+
+        film.write_stdout();
+        eprintln!("{}", stats);
+        Some(stats)
+    }
+
+    /// 渲染给定场景并返回图像缓冲（线性颜色）
+    pub fn render_film(
+        &self,
+        world: Arc<dyn Hittable + Send + Sync>,
+        lights: Arc<dyn Hittable + Send + Sync>,
+    ) -> Result<Film, CheckpointError> {
+        self.render_film_with_stats(world, lights)
+            .map(|(film, _)| film)
+    }
+
+    /// 渲染给定场景，返回图像缓冲和本次渲染的统计信息
+    ///
+    /// 断点恢复时只统计本次运行完成的采样
+    pub fn render_film_with_stats(
+        &self,
+        mut world: Arc<dyn Hittable + Send + Sync>,
+        lights: Arc<dyn Hittable + Send + Sync>,
+    ) -> Result<(Film, RenderStats), CheckpointError> {
+        self.render_refitted(&mut world, lights)
+    }
+
+    /// 先用快门区间重拟合 `world` 中的加速结构再渲染，重拟合后的场景写回 `world`
+    ///
+    /// 物体的 `bounding_box` 只覆盖默认的 [0, 1] 时间区间，快门在区间外时不重拟合会剪掉运动模糊；
+    /// 动画序列逐帧沿用上一帧的结果，重拟合的代价基准也随之保留
+    fn render_refitted(
+        &self,
+        world: &mut Arc<dyn Hittable + Send + Sync>,
+        lights: Arc<dyn Hittable + Send + Sync>,
+    ) -> Result<(Film, RenderStats), CheckpointError> {
+        let (shutter_open, shutter_close) = self.shutter();
+        if let Some(refitted) = world.refitted(Interval::new(shutter_open, shutter_close)) {
+            *world = refitted;
+        }
+        let world = Arc::clone(world);
+
         let mut camera = self.clone();
-        camera.initialize();
+        camera.initialize(&world);
+
+        let bvh = world.bvh_stats();
+        let (mut pixels, mut stats) = camera.render_pixels(world, lights)?;
+        stats.bvh = bvh;
+
+        if let Some(view) = camera.debug_view {
+            if view.is_heatmap() {
+                let max = apply_heatmap(&mut pixels);
+                eprintln!("\n{:?} 热图: 蓝色为 0，红色为 {:.1}", view, max);
+            }
+            let film = Film::new(
+                camera.image_width as usize,
+                camera.image_height as usize,
+                pixels,
+            );
+            return Ok((film, stats));
+        }
+
+        // 按曝光参数缩放胶片响应
+        let mut scale = camera.lens_scale;
+        if let Some(exposure) = &camera.exposure {
+            scale *= exposure.scale();
+        }
+        if scale != 1.0 {
+            for pixel in pixels.iter_mut() {
+                *pixel = scale * *pixel;
+            }
+        }
 
-        println!("P3\n{} {}\n255", camera.image_width, camera.image_height);
+        let film = Film::new(
+            camera.image_width as usize,
+            camera.image_height as usize,
+            pixels,
+        );
+        Ok((film, stats))
+    }
+
+    /// 渲染动画序列，第 `frame` 帧写入 `frame_path(out_pattern, frame)`
+    ///
+    /// 第 n 帧的快门在 n / frame_rate 时刻开启，持续 shutter_angle / 360 帧，
+    /// 快门期间相机参数按射线时间取关键帧值，相机与场景中随时间运动的物体都产生运动模糊。
+    /// 与单帧渲染相同，每帧渲染前用该帧的快门区间重拟合场景中的加速结构（见 `Hittable::refitted`）
+    pub fn render_sequence(
+        &self,
+        mut world: Arc<dyn Hittable + Send + Sync>,
+        lights: Arc<dyn Hittable + Send + Sync>,
+        frames: Range<i32>,
+        out_pattern: &str,
+    ) -> Result<(), CheckpointError> {
+        let total = frames.len();
+        for (index, frame) in frames.enumerate() {
+            let time = frame as f64 / self.frame_rate;
+
+            let mut camera = self.clone();
+            if let Some(animation) = &self.animation {
+                animation.apply(&mut camera, time);
+            }
+            camera.shutter_open = time;
+            camera.shutter_close = time + self.shutter_angle / 360.0 / self.frame_rate;
+
+            // 每帧各自保存断点
+            if let Some(path) = &self.checkpoint_path {
+                camera.checkpoint_path = Some(path_with_suffix(path, &frame.to_string()));
+            }
 
-        let camera_arc = Arc::new(camera);
+            eprintln!("\n渲染第 {} 帧 ({}/{})...", frame, index + 1, total);
+            let (film, stats) = camera.render_refitted(&mut world, Arc::clone(&lights))?;
+            eprintln!("\n{}", stats);
+
+            let path = frame_path(out_pattern, frame);
+            if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            film.write_ppm(&mut io::BufWriter::new(File::create(&path)?))?;
+        }
+
+        eprint!("\rDone.                 \n");
+        Ok(())
+    }
 
-        // 收集输出的互斥锁
-        let outputs: Mutex<Vec<String>> =
-            Mutex::new(vec![String::new(); camera_arc.image_height as usize]);
+    /// 按轮累积采样，返回求平均后的像素颜色（行优先）和射线统计
+    ///
+    /// 每一轮对每个像素采样分层网格中的一行（`sqrt_spp` 个样本），
+    /// 设置了 `checkpoint_path` 时每隔 `checkpoint_interval` 轮保存一次断点，
+    /// 全部完成后删除断点，下次运行重新渲染
+    fn render_pixels(
+        &self,
+        world: Arc<dyn Hittable + Send + Sync>,
+        lights: Arc<dyn Hittable + Send + Sync>,
+    ) -> Result<(Vec<Color>, RenderStats), CheckpointError> {
+        let width = self.image_width as usize;
+        let height = self.image_height as usize;
+
+        let scene_hash = match &self.checkpoint_path {
+            Some(_) => self.scene_hash(&world, &lights),
+            None => 0,
+        };
+
+        let mut state = match &self.checkpoint_path {
+            Some(path) if path.exists() => {
+                let checkpoint = Checkpoint::load(path)?;
+                checkpoint.validate(width, height, scene_hash)?;
+                eprintln!(
+                    "从断点恢复: 已完成 {}/{} 轮",
+                    checkpoint.passes_done, self.sqrt_spp
+                );
+                checkpoint
+            }
+            _ => Checkpoint::new(width, height, scene_hash, self.seed),
+        };
+
+        let seed = state.seed;
+        let total_passes = self.sqrt_spp.max(state.passes_done);
+        let total_rows = (total_passes - state.passes_done) as usize * height;
         let progress_counter = Arc::new(Mutex::new(0));
+        let counters = Mutex::new(RayCounters::default());
+        let start = Instant::now();
+
+        // 成束追踪时每个线程处理 packet_size 行，否则处理一行；调试视图逐条统计主射线
+        let packet_size = match self.debug_view {
+            Some(_) => 0,
+            None => self.packet_side(),
+        };
+        let band_rows = packet_size.max(1);
+
+        for pass in state.passes_done..self.sqrt_spp {
+            let s_j = pass;
+
+            state
+                .accum
+                .par_chunks_mut(width * band_rows)
+                .zip(state.sample_counts.par_chunks_mut(width * band_rows))
+                .enumerate()
+                .for_each(|(band, (rows, counts))| {
+                    // 按（轮次, 行）重设种子，使结果与线程调度无关，断点恢复后也能继续同一采样序列
+                    seed_thread_rng(mix_seed(seed, pass as u64, band as u64));
+                    // 丢弃该线程在渲染之外（如构建场景、自动对焦）累加的计数
+                    take_thread_counters();
+
+                    if packet_size > 1 {
+                        self.render_packets(band * band_rows, rows, s_j, &world, &lights);
+                    } else {
+                        let j = band;
+                        for (i, pixel) in rows.iter_mut().enumerate() {
+                            for s_i in 0..self.sqrt_spp {
+                                if let Some(r) = self.get_ray(i as i32, j as i32, s_i, s_j) {
+                                    *pixel += match self.debug_view {
+                                        Some(view) => self.debug_color(view, &r, &world, &lights),
+                                        None => self.ray_color(
+                                            &r,
+                                            self.max_depth,
+                                            Arc::clone(&world),
+                                            Arc::clone(&lights),
+                                        ),
+                                    };
+                                }
+                            }
+                        }
+                    }
+                    for count in counts.iter_mut() {
+                        *count += self.sqrt_spp as u32;
+                    }
+                    *counters.lock().unwrap() += take_thread_counters();
+
+                    // 更新进度
+                    let mut progress = progress_counter.lock().unwrap();
+                    *progress += rows.len() / width;
+                    eprint!(
+                        "\r渲染进度: {:.1}%",
+                        (*progress) as f64 / total_rows as f64 * 100.0
+                    );
+                    io::stderr().flush().unwrap();
+                });
+
+            state.passes_done = pass + 1;
+
+            if let Some(path) = &self.checkpoint_path {
+                let finished = state.passes_done == self.sqrt_spp;
+                if !finished && state.passes_done % self.checkpoint_interval.max(1) == 0 {
+                    state.save(path)?;
+                }
+            }
+        }
 
-        // 修改前
-        // let world_arc = Arc::new(world);
-        // let lights_arc = Arc::new(lights);
-        // 每个线程处理图像的一行
-        (0..camera_arc.image_height).into_par_iter().for_each(|j| {
-            let mut line_output = String::new();
-
-            for i in 0..camera_arc.image_width {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-
-                // for _ in 0..camera_arc.samples_per_pixel {
-                //     let r = camera_arc.get_ray(i, j);
-                //     pixel_color += camera_arc.ray_color(&r, camera_arc.max_depth, *world_arc);
-                // }
-
-                for s_j in 0..camera_arc.sqrt_spp {
-                    for s_i in 0..camera_arc.sqrt_spp {
-                        let r = camera_arc.get_ray(i, j, s_i, s_j);
-                        // pixel_color += camera_arc.ray_color(&r, camera_arc.max_depth, &**world_arc);
-
-                        // 修改ray_color之前的代码
-                        // pixel_color += camera_arc.ray_color(
-                        //     &r,
-                        //     camera_arc.max_depth,
-                        //     &**world_arc,
-                        //     &**lights_arc,
-                        // )
-
-                        // 修改ray_color之后
-                        pixel_color += camera_arc.ray_color(
-                            &r,
-                            camera_arc.max_depth,
-                            Arc::clone(&world),
-                            Arc::clone(&lights),
-                        )
+        if let Some(path) = self.checkpoint_path.as_ref().filter(|path| path.exists()) {
+            fs::remove_file(path)?;
+        }
+
+        let stats = RenderStats {
+            rays: counters.into_inner().unwrap(),
+            render_time: start.elapsed(),
+            bvh: None,
+        };
+        Ok((state.resolve(), stats))
+    }
+
+    /// 成束追踪的像素块边长，不超过射线束的容量
+    fn packet_side(&self) -> usize {
+        let max_side = (MAX_PACKET_SIZE as f64).sqrt() as i32;
+        self.packet_size.clamp(0, max_side) as usize
+    }
+
+    /// 以像素块为单位成束追踪第 `j0` 行起的若干行（`rows`）的主射线，次级射线仍逐条追踪
+    ///
+    /// 每个像素块对每个分层子格生成一束主射线，同一束中的射线都取各像素中相同的子格
+    fn render_packets(
+        &self,
+        j0: usize,
+        rows: &mut [Color],
+        s_j: i32,
+        world: &Arc<dyn Hittable + Send + Sync>,
+        lights: &Arc<dyn Hittable + Send + Sync>,
+    ) {
+        let width = self.image_width as usize;
+        let height = rows.len() / width;
+        let size = self.packet_side();
+
+        for i0 in (0..width).step_by(size) {
+            let block_width = size.min(width - i0);
+            for s_i in 0..self.sqrt_spp {
+                let mut rays = Vec::with_capacity(block_width * height);
+                let mut pixels = Vec::with_capacity(block_width * height);
+                for dj in 0..height {
+                    for i in i0..i0 + block_width {
+                        if let Some(r) = self.get_ray(i as i32, (j0 + dj) as i32, s_i, s_j) {
+                            rays.push(r);
+                            pixels.push(dj * width + i);
+                        }
                     }
                 }
 
-                let color_str =
-                    write_color_to_string(&(camera_arc.pixel_samples_scale * pixel_color));
-                line_output.push_str(&color_str);
+                let colors = self.packet_colors(rays, world, lights);
+                for (pixel, color) in pixels.into_iter().zip(colors) {
+                    rows[pixel] += color;
+                }
             }
+        }
+    }
 
-            // 更新进度
-            let mut progress = progress_counter.lock().unwrap();
-            *progress += 1;
-            eprint!(
-                "\r渲染进度: {:.1}%",
-                (*progress) as f64 / camera_arc.image_height as f64 * 100.0
-            );
-            io::stderr().flush().unwrap();
+    /// 一束主射线的颜色：整束求交后逐条着色
+    fn packet_colors(
+        &self,
+        rays: Vec<Ray>,
+        world: &Arc<dyn Hittable + Send + Sync>,
+        lights: &Arc<dyn Hittable + Send + Sync>,
+    ) -> Vec<Color> {
+        if self.max_depth <= 0 {
+            return vec![Color::new(0.0, 0.0, 0.0); rays.len()];
+        }
 
-            let mut outputs = outputs.lock().unwrap();
-            outputs[j as usize] = line_output;
-        });
+        count_primary_rays(rays.len());
+        let packet = RayPacket::new(rays);
+        let mut hits = PacketHits::new(&packet, Interval::new(0.001, INFINITY));
+        world.hit_packet(&packet, packet.full_mask(), &mut hits);
+
+        packet
+            .rays()
+            .iter()
+            .enumerate()
+            .map(|(k, r)| {
+                if !hits.is_hit(k) {
+                    return self.background;
+                }
+                let rec = std::mem::take(&mut hits.recs[k]);
+                self.shade(
+                    r,
+                    rec,
+                    self.max_depth,
+                    Arc::clone(world),
+                    Arc::clone(lights),
+                )
+            })
+            .collect()
+    }
 
-        let outputs = outputs.into_inner().unwrap();
-        for output in outputs {
-            print!("{}", output);
+    /// 场景与相机参数的哈希，用于判断断点是否仍然有效
+    ///
+    /// 只哈希影响累积缓冲的参数：初始化后的视口几何、采样设置、投影、光圈、快门与镜头数据，
+    /// 以及场景和光源的内容（几何与材质参数，见 `Hittable::hash_content`）。
+    /// 另外从相机发出一组探测射线，哈希命中距离、法向与材质类型，只作为补充检查，
+    /// 不能单独发现探测射线没有经过的改动。曝光只在输出时缩放，不影响断点。
+    /// 随机生成的场景需要先用 `seed_thread_rng` 固定种子，否则每次运行都会被视为不同的场景
+    fn scene_hash(
+        &self,
+        world: &Arc<dyn Hittable + Send + Sync>,
+        lights: &Arc<dyn Hittable + Send + Sync>,
+    ) -> u64 {
+        let mut hasher = Fnv64::new();
+
+        // 相机
+        hasher.write_u64(self.image_width as u64);
+        hasher.write_u64(self.image_height as u64);
+        hasher.write_u64(self.sqrt_spp as u64);
+        hasher.write_u64(self.max_depth as u64);
+        hasher.write_u64(self.seed);
+        hasher.write_vec3(&self.background);
+        for v in [
+            &self.center,
+            &self.pixel00_loc,
+            &self.pixel_delta_u,
+            &self.pixel_delta_v,
+            &self.u,
+            &self.v,
+            &self.w,
+            &self.defocus_disk_u,
+            &self.defocus_disk_v,
+            &self.focus_plane_normal,
+        ] {
+            hasher.write_vec3(v);
+        }
+        for x in [
+            self.shutter_interval.0,
+            self.shutter_interval.1,
+            self.cat_eye,
+            self.eye_offset,
+            self.convergence_dist,
+            self.lens_scale,
+        ] {
+            hasher.write_f64(x);
         }
 
-        eprint!("\rDone.                 \n");
-        io::stderr().flush().unwrap();
+        match self.projection {
+            Projection::Perspective => hasher.write_u64(0),
+            Projection::Orthographic { height } => {
+                hasher.write_u64(1);
+                hasher.write_f64(height);
+            }
+            Projection::Equirectangular => hasher.write_u64(2),
+            Projection::Fisheye { fov, mapping } => {
+                hasher.write_u64(3);
+                hasher.write_f64(fov);
+                hasher.write_u64(mapping as u64);
+            }
+        }
+        match &self.aperture {
+            Aperture::Circular => hasher.write_u64(0),
+            Aperture::Polygon { blades, rotation } => {
+                hasher.write_u64(1);
+                hasher.write_u64(*blades as u64);
+                hasher.write_f64(*rotation);
+            }
+            Aperture::Mask(image) => {
+                hasher.write_u64(2);
+                hasher.write_u64(image.width() as u64);
+                hasher.write_u64(image.height() as u64);
+            }
+        }
+        match self.shutter_curve {
+            ShutterCurve::Box => hasher.write_u64(0),
+            ShutterCurve::Trapezoid { ramp } => {
+                hasher.write_u64(1);
+                hasher.write_f64(ramp);
+            }
+            ShutterCurve::Rolling { readout } => {
+                hasher.write_u64(2);
+                hasher.write_f64(readout);
+            }
+        }
+        if let Some(lens) = &self.lens {
+            hasher.write_f64(lens.film_diagonal);
+            hasher.write_f64(lens.units_per_meter);
+            for element in &lens.elements {
+                hasher.write_f64(element.curvature_radius);
+                hasher.write_f64(element.thickness);
+                hasher.write_f64(element.ior);
+                hasher.write_f64(element.aperture_radius);
+            }
+        }
+        hasher.write_u64(self.debug_view.map_or(0, |view| view as u64 + 1));
+        for camera in self.motion.iter() {
+            hasher.write_vec3(&camera.center);
+            hasher.write_vec3(&camera.pixel00_loc);
+            hasher.write_vec3(&camera.pixel_delta_u);
+            hasher.write_vec3(&camera.pixel_delta_v);
+        }
+
+        // 场景
+        let mut scene = SceneHasher::new();
+        world.hash_content(&mut scene);
+        lights.hash_content(&mut scene);
+        hasher.write_u64(scene.finish());
+
+        // 探测射线：从相机中心向视野内 8×8 个方向发出，时间取快门开启时刻
+        const PROBES: usize = 8;
+        for j in 0..PROBES {
+            for i in 0..PROBES {
+                let a = 2.0 * (i as f64 + 0.5) / PROBES as f64 - 1.0;
+                let b = 2.0 * (j as f64 + 0.5) / PROBES as f64 - 1.0;
+                let r = Ray::with_origin_dir_time(
+                    self.center,
+                    a * self.u + b * self.v - self.w,
+                    self.shutter_interval.0,
+                );
+                let mut rec = HitRecord::default();
+                if world.hit(&r, Interval::new(0.001, INFINITY), &mut rec) {
+                    hasher.write_f64(rec.t);
+                    hasher.write_vec3(&rec.normal);
+                    hasher.write_bytes(rec.mat.as_ref().map_or("", |m| m.name()).as_bytes());
+                } else {
+                    hasher.write_u64(u64::MAX);
+                }
+            }
+        }
+
+        hasher.finish()
     }
 
-    /// 初始化相机内部参数
-    fn initialize(&mut self) {
+    /// 初始化相机内部参数，设置了自动对焦时先由场景求出对焦距离
+    fn initialize(&mut self, world: &Arc<dyn Hittable + Send + Sync>) {
         // 计算图像高度
         self.image_height = (self.image_width as f64 / self.aspect_ratio) as i32;
         self.image_height = if self.image_height < 1 {
@@ -168,24 +651,71 @@ impl Camera {
         };
 
         self.sqrt_spp = (self.samples_per_pixel as f64).sqrt() as i32;
-        self.pixel_samples_scale = 1.0 / ((self.sqrt_spp * self.sqrt_spp) as f64);
         self.recip_sqrt_spp = 1.0 / (self.sqrt_spp as f64);
 
         // self.pixel_samples_scale = 1.0 / (self.samples_per_pixel as f64);
 
+        self.w = unit_vector(self.lookfrom - self.lookat);
+        self.u = unit_vector(cross(&self.vup, &self.w));
+        self.v = cross(&self.w, &self.u);
+
+        self.setup_view();
+
+        // 自动对焦：用当前参数生成对焦射线，求出距离后按新的 focus_dist 重新计算
+        if let Some(target) = self.focus_target {
+            match self.auto_focus_distance(target, world) {
+                Some(dist) => {
+                    self.focus_dist = dist;
+                    self.setup_view();
+                }
+                None => eprintln!(
+                    "Autofocus found nothing at {:?}, keeping focus_dist",
+                    target
+                ),
+            }
+        }
+
+        // 相机动画：预先求出快门区间内各时刻的相机，渲染时按射线时间选用
+        self.motion = Arc::new(Vec::new());
+        let (shutter_open, shutter_close) = self.shutter_interval;
+        if let Some(animation) = self
+            .animation
+            .clone()
+            .filter(|_| shutter_close > shutter_open)
+        {
+            let motion = (0..MOTION_STEPS)
+                .map(|k| {
+                    let time = shutter_open
+                        + (shutter_close - shutter_open) * k as f64 / (MOTION_STEPS - 1) as f64;
+                    let mut camera = self.clone();
+                    camera.animation = None;
+                    animation.apply(&mut camera, time);
+                    camera.initialize(world);
+                    camera
+                })
+                .collect();
+            self.motion = Arc::new(motion);
+        }
+    }
+
+    /// 按 focus_dist 计算视口、眼睛偏移、焦平面、快门区间与镜头对焦
+    ///
+    /// 需要在 u、v、w 确定之后调用，可以重复调用
+    fn setup_view(&mut self) {
         // 设置相机中心
         self.center = self.lookfrom;
 
         // 计算视口尺寸
-        let theta = degrees_to_radians(self.vfov);
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * self.focus_dist;
+        let viewport_height = match self.projection {
+            Projection::Orthographic { height } => height,
+            _ => {
+                let theta = degrees_to_radians(self.vfov);
+                let h = (theta / 2.0).tan();
+                2.0 * h * self.focus_dist
+            }
+        };
         let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
 
-        self.w = unit_vector(self.lookfrom - self.lookat);
-        self.u = unit_vector(cross(&self.vup, &self.w));
-        self.v = cross(&self.w, &self.u);
-
         // 计算视口边缘向量
         let viewport_u = viewport_width * self.u;
         let viewport_v = -viewport_height * self.v;
@@ -194,34 +724,248 @@ impl Camera {
         self.pixel_delta_u = viewport_u / (self.image_width as f64);
         self.pixel_delta_v = viewport_v / (self.image_height as f64);
 
+        // 立体渲染：眼睛沿 u 平移，视口反向错位使两眼画面在会聚距离处重合
+        let mut viewport_shift = Vec3::default();
+        if self.eye_offset != 0.0 && self.projection.uses_viewport() {
+            self.center += self.eye_offset * self.u;
+            if let Projection::Perspective = self.projection {
+                viewport_shift =
+                    -(self.eye_offset * self.focus_dist / self.convergence_dist) * self.u;
+            }
+        }
+
+        // 移轴：平移视口而不旋转相机坐标系，竖直线保持平行
+        viewport_shift += self.lens_shift_x * viewport_width * self.u
+            + self.lens_shift_y * viewport_height * self.v;
+
         // 计算视口左上角位置
         let viewport_upper_left =
-            self.center - (self.focus_dist * self.w) - viewport_u / 2.0 - viewport_v / 2.0;
+            self.center - (self.focus_dist * self.w) - viewport_u / 2.0 - viewport_v / 2.0
+                + viewport_shift;
         self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
+        // 倾角：焦平面绕 u / v 轴旋转（沙姆定律），仍经过光轴上距离 focus_dist 的点
+        self.focus_plane_normal = unit_vector(
+            self.w
+                + degrees_to_radians(self.lens_tilt_x).tan() * self.v
+                + degrees_to_radians(self.lens_tilt_y).tan() * self.u,
+        );
+
+        // 物理曝光参数可以接管景深与运动模糊
+        self.shutter_interval = self.shutter();
+        if let Some(exposure) = &self.exposure
+            && exposure.drive_defocus
+        {
+            self.defocus_angle = exposure.defocus_angle(self.vfov, self.focus_dist);
+        }
+
         let defocus_radius = self.focus_dist * (degrees_to_radians(self.defocus_angle / 2.0).tan());
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
+
+        // 真实镜头：移动后镜片对焦到 focus_dist，并以画面中心的通光比例归一化亮度
+        if let Some(lens) = &self.lens {
+            let mut lens = (**lens).clone();
+            if let Err(e) = lens.focus(self.focus_dist) {
+                eprintln!("Lens focus failed: {}", e);
+            }
+            let transmittance = lens.center_transmittance();
+            self.lens_scale = if transmittance > 0.0 {
+                1.0 / transmittance
+            } else {
+                1.0
+            };
+            self.lens = Some(Arc::new(lens));
+        }
     }
 
-    fn get_ray(&self, i: i32, j: i32, s_i: i32, s_j: i32) -> Ray {
+    /// 射线时间的取值区间，物理曝光参数接管运动模糊时由快门时间决定
+    fn shutter(&self) -> (f64, f64) {
+        match &self.exposure {
+            Some(exposure) if exposure.drive_motion_blur => {
+                (self.shutter_open, self.shutter_open + exposure.shutter_time)
+            }
+            _ => (self.shutter_open, self.shutter_close),
+        }
+    }
+
+    /// 向对焦目标投射一条射线，返回命中点沿光轴（-w）到相机平面的距离
+    ///
+    /// 像素目标使用与渲染相同的射线生成（`primary_ray`），取像素中心、光圈中心和快门开启时刻，
+    /// 因此投影、移轴、立体眼睛偏移和真实镜头都会生效。需要在 `setup_view` 之后调用
+    fn auto_focus_distance(
+        &self,
+        target: FocusTarget,
+        world: &Arc<dyn Hittable + Send + Sync>,
+    ) -> Option<f64> {
+        let time = self.shutter_interval.0;
+        let (r, max_t) = match target {
+            FocusTarget::Pixel(i, j) => (
+                self.primary_ray(i, j, Vec3::default(), time, false)?,
+                INFINITY,
+            ),
+            FocusTarget::Point(p) => (
+                Ray::with_origin_dir_time(self.center, p - self.center, time),
+                1.0,
+            ),
+        };
+
+        let mut rec = crate::hittable::HitRecord::default();
+        let hit_point = if world.hit(&r, Interval::new(0.001, max_t), &mut rec) {
+            rec.p
+        } else {
+            match target {
+                FocusTarget::Pixel(..) => return None,
+                FocusTarget::Point(p) => p,
+            }
+        };
+
+        let dist = dot(&(hit_point - self.center), &-self.w);
+        if dist > 0.0 { Some(dist) } else { None }
+    }
+
+    /// 生成穿过像素 (i, j) 中第 (s_i, s_j) 个分层子格的射线
+    ///
+    /// 鱼眼投影在图像圆外没有射线，返回 `None`（该样本记为黑色）
+    fn get_ray(&self, i: i32, j: i32, s_i: i32, s_j: i32) -> Option<Ray> {
         // 在像素区域内随机采样
         let offset = self.sample_square_stratified(s_i, s_j);
         // let offset = self.sample_square();
+        let (shutter_open, shutter_close) = self.shutter_interval;
+        let row = (j as f64 + 0.5) / self.image_height as f64;
+        let ray_time =
+            self.shutter_curve
+                .sample_time(shutter_open, shutter_close, row, random_double());
+
+        if !self.motion.is_empty() {
+            // 在 ray_time 两侧的两个时刻中按距离随机选一个，图像的期望等于两者的线性插值
+            let x = (ray_time - shutter_open) / (shutter_close - shutter_open)
+                * (self.motion.len() - 1) as f64;
+            let x = x.clamp(0.0, (self.motion.len() - 1) as f64);
+            let mut k = x.floor() as usize;
+            if random_double() < x - k as f64 {
+                k += 1;
+            }
+            return self.motion[k].primary_ray(i, j, offset, ray_time, true);
+        }
+
+        self.primary_ray(i, j, offset, ray_time, true)
+    }
+
+    /// 穿过像素 (i, j) 中偏移 `offset` 处的射线
+    ///
+    /// `sample_aperture` 为 false 时射线经过光圈（或后镜片）中心，不产生景深模糊
+    fn primary_ray(
+        &self,
+        i: i32,
+        j: i32,
+        offset: Vec3,
+        ray_time: f64,
+        sample_aperture: bool,
+    ) -> Option<Ray> {
+        if !self.projection.uses_viewport() {
+            // 全景类投影：由图像坐标直接得到方向，不使用景深
+            let sx = (i as f64 + 0.5 + offset.x()) / self.image_width as f64;
+            let sy = (j as f64 + 0.5 + offset.y()) / self.image_height as f64;
+            let aspect = self.image_width as f64 / self.image_height as f64;
+            let local = self.projection.local_direction(sx, sy, aspect)?;
+            let mut ray_direction = local.x() * self.u + local.y() * self.v + local.z() * self.w;
+            let mut ray_origin = self.center;
+
+            if self.eye_offset != 0.0 {
+                // 全向立体：眼睛位于水平圆上，偏移方向垂直于当前视线的水平分量
+                let horizontal = Vec3::new(local.x(), 0.0, local.z());
+                let side = if horizontal.length_squared() > 1e-12 {
+                    let h = unit_vector(horizontal);
+                    -h.z() * self.u + h.x() * self.w
+                } else {
+                    self.u
+                };
+                let eye = self.eye_offset * side;
+                ray_origin = self.center + eye;
+                if self.convergence_dist.is_finite() {
+                    ray_direction = self.convergence_dist * unit_vector(ray_direction) - eye;
+                }
+            }
+
+            return Some(Ray::with_origin_dir_time(
+                ray_origin,
+                ray_direction,
+                ray_time,
+            ));
+        }
+
+        if let (Some(lens), Projection::Perspective) = (&self.lens, &self.projection) {
+            return self.get_lens_ray(lens, i, j, offset, ray_time, sample_aperture);
+        }
+
         let pixel_sample = self.pixel00_loc
             + ((i as f64 + offset.x()) * self.pixel_delta_u)
             + ((j as f64 + offset.y()) * self.pixel_delta_v);
 
+        // 正交投影的射线起点在相机平面上与像素对应的位置
+        let lens_center = match self.projection {
+            Projection::Orthographic { .. } => pixel_sample + self.focus_dist * self.w,
+            _ => self.center,
+        };
+
         // 构建射线
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
+        let ray_origin = if !sample_aperture || self.defocus_angle <= 0.0 {
+            lens_center
         } else {
-            self.defocus_disk_sample()
+            // 像素在画面中的位置（中心为 0），用于猫眼渐晕
+            let film_x = (i as f64 + 0.5 + offset.x()) / self.image_width as f64 * 2.0 - 1.0;
+            let film_y = 1.0 - (j as f64 + 0.5 + offset.y()) / self.image_height as f64 * 2.0;
+            lens_center + self.defocus_disk_offset(film_x, film_y)?
         };
-        let ray_direction = pixel_sample - ray_origin;
-        let ray_time = random_double();
+        let ray_direction = self.focus_point(lens_center, pixel_sample) - ray_origin;
+
+        Some(Ray::with_origin_dir_time(
+            ray_origin,
+            ray_direction,
+            ray_time,
+        ))
+    }
 
-        Ray::with_origin_dir_time(ray_origin, ray_direction, ray_time)
+    /// 从胶片上的采样点向后镜片随机一点（`sample_aperture` 为 false 时为中心）发出射线，
+    /// 穿过整组镜头后转到世界空间
+    ///
+    /// 镜头成倒像，因此画面左上角对应胶片右下角；被镜片边缘或光阑挡住的样本返回 `None`
+    fn get_lens_ray(
+        &self,
+        lens: &LensSystem,
+        i: i32,
+        j: i32,
+        offset: Vec3,
+        ray_time: f64,
+        sample_aperture: bool,
+    ) -> Option<Ray> {
+        let aspect = self.image_width as f64 / self.image_height as f64;
+        let half_height = 0.5 * lens.film_diagonal / (aspect * aspect + 1.0).sqrt();
+        let half_width = aspect * half_height;
+
+        let ndc_x = (i as f64 + 0.5 + offset.x()) / self.image_width as f64 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (j as f64 + 0.5 + offset.y()) / self.image_height as f64 * 2.0;
+        let film_point = Point3::new(-ndc_x * half_width, -ndc_y * half_height, 0.0);
+
+        let p = if sample_aperture {
+            lens.rear_aperture_radius() * random_in_unit_disk()
+        } else {
+            Vec3::default()
+        };
+        let rear_point = Point3::new(p.x(), p.y(), lens.rear_z());
+
+        let r = Ray::with_origin_dir_time(film_point, rear_point - film_point, ray_time);
+        let r = lens.trace_from_film(&r)?;
+
+        // 镜头空间（毫米，+z 指向场景）到世界空间
+        let mm_to_world = lens.units_per_meter / 1000.0;
+        let to_world = |v: &Vec3| v.x() * self.u + v.y() * self.v - v.z() * self.w;
+        Some(Ray::with_origin_dir_time(
+            self.center + mm_to_world * to_world(r.origin()),
+            to_world(r.direction()),
+            ray_time,
+        ))
     }
 
     fn sample_square_stratified(&self, s_i: i32, s_j: i32) -> Vec3 {
@@ -234,9 +978,46 @@ impl Camera {
         Vec3::new(random_double() - 0.5, random_double() - 0.5, 0.0)
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = random_in_unit_disk();
-        self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v)
+    /// 经过透镜中心和像素采样点的射线与焦平面的交点
+    ///
+    /// 焦平面未倾斜时就是视口上的像素采样点本身
+    fn focus_point(&self, lens_center: Point3, pixel_sample: Point3) -> Point3 {
+        if self.lens_tilt_x == 0.0 && self.lens_tilt_y == 0.0 {
+            return pixel_sample;
+        }
+
+        let plane_point = self.center - self.focus_dist * self.w;
+        let direction = pixel_sample - lens_center;
+        let denom = dot(&direction, &self.focus_plane_normal);
+        if denom.abs() < 1e-12 {
+            return pixel_sample;
+        }
+
+        let t = dot(&(plane_point - lens_center), &self.focus_plane_normal) / denom;
+        if t <= 0.0 {
+            return pixel_sample;
+        }
+        lens_center + t * direction
+    }
+
+    /// 光圈上随机一点相对透镜中心的偏移
+    ///
+    /// 开启猫眼渐晕时，光圈被一个随画面位置偏移的圆裁切（模拟镜筒遮挡），
+    /// 落在裁切区域外的样本被挡住，返回 `None`
+    fn defocus_disk_offset(&self, film_x: f64, film_y: f64) -> Option<Vec3> {
+        let (px, py) = self.aperture.sample();
+
+        if self.cat_eye > 0.0 {
+            let aspect = self.image_width as f64 / self.image_height as f64;
+            let half_diagonal = (aspect * aspect + 1.0).sqrt();
+            let shift_x = self.cat_eye * film_x * aspect / half_diagonal;
+            let shift_y = self.cat_eye * film_y / half_diagonal;
+            if (px - shift_x).powi(2) + (py - shift_y).powi(2) > 1.0 {
+                return None;
+            }
+        }
+
+        Some((px * self.defocus_disk_u) + (py * self.defocus_disk_v))
     }
 
     /// 计算射线与场景交互后的颜色
@@ -253,12 +1034,74 @@ impl Camera {
             return Color::new(0.0, 0.0, 0.0);
         }
 
-        let mut rec = crate::hittable::HitRecord::default();
+        if depth == self.max_depth {
+            count_primary_rays(1);
+        } else {
+            count_secondary_ray();
+        }
+
+        let mut rec = HitRecord::default();
 
         if !world.hit(r, Interval::new(0.001, INFINITY), &mut rec) {
             return self.background;
         }
 
+        self.shade(r, rec, depth, world, lights)
+    }
+
+    /// 调试视图中一条主射线的值
+    ///
+    /// 数值视图由遍历前后当前线程计数器的差得到，返回三个分量相同的颜色，渲染结束后再上色
+    fn debug_color(
+        &self,
+        view: DebugView,
+        r: &Ray,
+        world: &Arc<dyn Hittable + Send + Sync>,
+        lights: &Arc<dyn Hittable + Send + Sync>,
+    ) -> Color {
+        let before = thread_counters();
+        let mut rec = HitRecord::default();
+
+        let value = match view {
+            DebugView::PathDepth => {
+                self.ray_color(r, self.max_depth, Arc::clone(world), Arc::clone(lights));
+                thread_counters().secondary_rays - before.secondary_rays
+            }
+            DebugView::NodesVisited | DebugView::PrimitiveTests => {
+                count_primary_rays(1);
+                world.hit(r, Interval::new(0.001, INFINITY), &mut rec);
+                let after = thread_counters();
+                if view == DebugView::NodesVisited {
+                    after.nodes_visited - before.nodes_visited
+                } else {
+                    after.primitive_tests - before.primitive_tests
+                }
+            }
+            DebugView::Material => {
+                count_primary_rays(1);
+                if !world.hit(r, Interval::new(0.001, INFINITY), &mut rec) {
+                    return Color::new(0.0, 0.0, 0.0);
+                }
+                return rec
+                    .mat
+                    .as_ref()
+                    .map_or(Color::new(0.0, 0.0, 0.0), |mat| material_color(mat.name()));
+            }
+        };
+
+        let value = value as f64;
+        Color::new(value, value, value)
+    }
+
+    /// 射线在 `rec` 处击中物体后的颜色：自发光加上散射光
+    fn shade(
+        &self,
+        r: &Ray,
+        rec: HitRecord,
+        depth: i32,
+        world: Arc<dyn Hittable + Send + Sync>,
+        lights: Arc<dyn Hittable + Send + Sync>,
+    ) -> Color {
         let mut srec = ScatterRecord::default();
 
         let color_from_emission = rec.mat.as_ref().map_or(Color::default(), |mat| {
@@ -302,7 +1145,8 @@ impl Camera {
         let mixed_pdf = MixturePdf::new(light_pdf_arc, mat_pdf_arc);
 
         let scattered_dir = mixed_pdf.generate();
-        let scattered = Ray::with_origin_dir_time(rec.p, scattered_dir, r.time());
+        let scattered =
+            Ray::with_origin_dir_time(rec.spawn_origin(&scattered_dir), scattered_dir, r.time());
         let pdf_value = mixed_pdf.value(scattered.direction());
 
         let scattering_pdf = rec
@@ -0,0 +1,328 @@
+use crate::color::Color;
+use crate::material::Material;
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// 断点文件头部的魔数与版本号
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 1;
+
+/// 文件头（魔数、版本、宽、高、哈希、种子、轮数）与每个像素（颜色和、样本数）的字节数
+const HEADER_BYTES: u64 = 4 + 4 + 8 + 8 + 8 + 8 + 4;
+const PIXEL_BYTES: u64 = 3 * 8 + 4;
+
+/// 长时间渲染的断点
+///
+/// 保存累积缓冲（未除以样本数的颜色和）、逐像素样本数、随机数种子、
+/// 已完成的采样轮数以及场景/相机哈希，用于下次运行时继续渲染
+///
+/// 不保存随机数生成器的内部状态：渲染时每一轮的每一行都按 (seed, 轮次, 行) 重设种子
+/// （见 `rtweekend::mix_seed`），因此只凭 seed 和已完成的轮数就能接着产生同一采样序列
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub width: usize,
+    pub height: usize,
+    pub scene_hash: u64,
+    pub seed: u64,
+    pub passes_done: i32, // 分层网格中已完成的行数，等于 sqrt_spp 时渲染完成
+    pub accum: Vec<Color>,
+    pub sample_counts: Vec<u32>,
+}
+
+/// 读取或校验断点时的错误
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    BadFormat(&'static str),
+    SizeMismatch {
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+    SceneChanged {
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "checkpoint I/O error: {}", e),
+            CheckpointError::BadFormat(msg) => write!(f, "invalid checkpoint file: {}", msg),
+            CheckpointError::SizeMismatch { expected, found } => write!(
+                f,
+                "checkpoint is {}x{} but the camera renders {}x{}",
+                found.0, found.1, expected.0, expected.1
+            ),
+            CheckpointError::SceneChanged { expected, found } => write!(
+                f,
+                "scene or camera changed since the checkpoint was written (hash {:016x}, checkpoint {:016x})",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(e: io::Error) -> Self {
+        CheckpointError::Io(e)
+    }
+}
+
+impl Checkpoint {
+    /// 创建一个空断点（尚未完成任何采样）
+    pub fn new(width: usize, height: usize, scene_hash: u64, seed: u64) -> Self {
+        Self {
+            width,
+            height,
+            scene_hash,
+            seed,
+            passes_done: 0,
+            accum: vec![Color::default(); width * height],
+            sample_counts: vec![0; width * height],
+        }
+    }
+
+    /// 检查断点是否属于当前场景和图像尺寸
+    pub fn validate(
+        &self,
+        width: usize,
+        height: usize,
+        scene_hash: u64,
+    ) -> Result<(), CheckpointError> {
+        if (self.width, self.height) != (width, height) {
+            return Err(CheckpointError::SizeMismatch {
+                expected: (width, height),
+                found: (self.width, self.height),
+            });
+        }
+        if self.scene_hash != scene_hash {
+            return Err(CheckpointError::SceneChanged {
+                expected: scene_hash,
+                found: self.scene_hash,
+            });
+        }
+        Ok(())
+    }
+
+    /// 写入断点文件
+    ///
+    /// 先写临时文件再重命名，避免写到一半时崩溃留下损坏的断点
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut out = BufWriter::new(File::create(&tmp_path)?);
+            out.write_all(MAGIC)?;
+            out.write_all(&VERSION.to_le_bytes())?;
+            out.write_all(&(self.width as u64).to_le_bytes())?;
+            out.write_all(&(self.height as u64).to_le_bytes())?;
+            out.write_all(&self.scene_hash.to_le_bytes())?;
+            out.write_all(&self.seed.to_le_bytes())?;
+            out.write_all(&self.passes_done.to_le_bytes())?;
+            for (color, count) in self.accum.iter().zip(&self.sample_counts) {
+                out.write_all(&color.x().to_le_bytes())?;
+                out.write_all(&color.y().to_le_bytes())?;
+                out.write_all(&color.z().to_le_bytes())?;
+                out.write_all(&count.to_le_bytes())?;
+            }
+            out.flush()?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+
+    /// 读取断点文件
+    ///
+    /// 在分配缓冲之前先用文件长度校验头部中的图像尺寸，损坏的文件不会导致巨大的分配
+    pub fn load(path: &Path) -> Result<Self, CheckpointError> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(CheckpointError::BadFormat("wrong magic number"));
+        }
+        if read_u32(&mut input)? != VERSION {
+            return Err(CheckpointError::BadFormat("unsupported version"));
+        }
+
+        let width = read_u64(&mut input)? as usize;
+        let height = read_u64(&mut input)? as usize;
+        let scene_hash = read_u64(&mut input)?;
+        let seed = read_u64(&mut input)?;
+        let passes_done = read_u32(&mut input)? as i32;
+
+        let pixel_count = width
+            .checked_mul(height)
+            .ok_or(CheckpointError::BadFormat("image size overflow"))?;
+        let expected_len = (pixel_count as u64)
+            .checked_mul(PIXEL_BYTES)
+            .and_then(|n| n.checked_add(HEADER_BYTES));
+        if expected_len != Some(file_len) {
+            return Err(CheckpointError::BadFormat(
+                "file size does not match the image size in the header",
+            ));
+        }
+        let mut accum = Vec::with_capacity(pixel_count);
+        let mut sample_counts = Vec::with_capacity(pixel_count);
+        for _ in 0..pixel_count {
+            let r = read_f64(&mut input)?;
+            let g = read_f64(&mut input)?;
+            let b = read_f64(&mut input)?;
+            accum.push(Color::new(r, g, b));
+            sample_counts.push(read_u32(&mut input)?);
+        }
+
+        Ok(Self {
+            width,
+            height,
+            scene_hash,
+            seed,
+            passes_done,
+            accum,
+            sample_counts,
+        })
+    }
+
+    /// 按样本数求平均后的像素颜色
+    pub fn resolve(&self) -> Vec<Color> {
+        self.accum
+            .iter()
+            .zip(&self.sample_counts)
+            .map(|(color, &count)| {
+                if count == 0 {
+                    Color::default()
+                } else {
+                    *color / count as f64
+                }
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    let mut buf = [0u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+/// FNV-1a 64 位哈希
+///
+/// 与 `DefaultHasher` 不同，它的结果在不同 Rust 版本之间保持稳定
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv64(u64);
+
+impl Fnv64 {
+    pub fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn write_u64(&mut self, x: u64) {
+        self.write_bytes(&x.to_le_bytes());
+    }
+
+    pub fn write_f64(&mut self, x: f64) {
+        self.write_bytes(&x.to_le_bytes());
+    }
+
+    pub fn write_vec3(&mut self, v: &Vec3) {
+        self.write_f64(v.x());
+        self.write_f64(v.y());
+        self.write_f64(v.z());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Write for Fnv64 {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// 场景内容的哈希，由各物体的 `Hittable::hash_content` 写入
+///
+/// 材质按 `Debug` 输出（含纹理与图像数据）哈希，被许多物体共用的材质只格式化一次
+#[derive(Debug, Default)]
+pub struct SceneHasher {
+    hasher: Fnv64,
+    materials: HashMap<usize, u64>, // 材质地址 -> 内容哈希
+}
+
+impl SceneHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 类型标记，避免不同类型的物体恰好写入相同的数据
+    pub fn write_tag(&mut self, tag: &str) {
+        self.hasher.write_bytes(tag.as_bytes());
+        self.hasher.write_u64(tag.len() as u64);
+    }
+
+    pub fn write_u64(&mut self, x: u64) {
+        self.hasher.write_u64(x);
+    }
+
+    pub fn write_f64(&mut self, x: f64) {
+        self.hasher.write_f64(x);
+    }
+
+    pub fn write_vec3(&mut self, v: &Vec3) {
+        self.hasher.write_vec3(v);
+    }
+
+    pub fn write_material(&mut self, material: &dyn Material) {
+        let address = material as *const dyn Material as *const () as usize;
+        let hash = *self.materials.entry(address).or_insert_with(|| {
+            let mut hasher = Fnv64::new();
+            let _ = fmt::Write::write_fmt(&mut hasher, format_args!("{:?}", material));
+            hasher.finish()
+        });
+        self.hasher.write_u64(hash);
+    }
+
+    /// 按 `Debug` 输出哈希，不必先拼成字符串
+    pub fn write_debug<T: fmt::Debug + ?Sized>(&mut self, value: &T) {
+        let _ = fmt::Write::write_fmt(&mut self.hasher, format_args!("{:?}", value));
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hasher.finish()
+    }
+}
+
+impl Default for Fnv64 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
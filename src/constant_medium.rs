@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use crate::{
+    checkpoint::SceneHasher,
     color::Color,
     hittable::{HitRecord, Hittable},
     interval::Interval,
@@ -98,6 +99,7 @@ impl Hittable for ConstantMedium {
 
         rec.normal = Vec3::new(1.0, 0.0, 0.0);
         rec.front_face = true;
+        rec.geometric_normal = rec.normal;
         rec.mat = Some(self.phase_function.clone());
 
         true
@@ -106,4 +108,11 @@ impl Hittable for ConstantMedium {
     fn bounding_box(&self) -> crate::aabb::Aabb {
         self.boundary.bounding_box()
     }
+
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("constant_medium");
+        self.boundary.hash_content(hasher);
+        hasher.write_f64(self.neg_inv_density);
+        hasher.write_material(self.phase_function.as_ref());
+    }
 }
@@ -0,0 +1,86 @@
+use crate::checkpoint::Fnv64;
+use crate::color::Color;
+
+/// 调试用的渲染模式，输出伪彩色图像而不是辐射亮度
+///
+/// 数值视图先按样本求平均，渲染结束后按全图最大值归一化，再映射为从蓝到红的热图
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    /// 每条主射线访问的 BVH 节点数
+    NodesVisited,
+    /// 每条主射线的图元求交次数
+    PrimitiveTests,
+    /// 路径终止前的反弹次数
+    PathDepth,
+    /// 主射线击中的材质类型，每种材质一种颜色（见 `material_color`），未击中为黑色
+    Material,
+}
+
+impl DebugView {
+    /// 是否为需要归一化并上色的数值视图
+    pub fn is_heatmap(&self) -> bool {
+        !matches!(self, DebugView::Material)
+    }
+}
+
+/// 把 [0, 1] 内的值映射为蓝、青、绿、黄、红依次过渡的颜色
+pub fn heatmap(t: f64) -> Color {
+    const STOPS: [(f64, f64, f64); 5] = [
+        (0.0, 0.0, 1.0),
+        (0.0, 1.0, 1.0),
+        (0.0, 1.0, 0.0),
+        (1.0, 1.0, 0.0),
+        (1.0, 0.0, 0.0),
+    ];
+
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as f64;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    Color::new(
+        a.0 + (b.0 - a.0) * f,
+        a.1 + (b.1 - a.1) * f,
+        a.2 + (b.2 - a.2) * f,
+    )
+}
+
+/// 把数值视图的像素（三个分量相同）按最大值归一化后映射为热图颜色，返回最大值
+pub fn apply_heatmap(pixels: &mut [Color]) -> f64 {
+    let max = pixels.iter().map(|p| p.x()).fold(0.0, f64::max);
+    let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+    for pixel in pixels.iter_mut() {
+        *pixel = heatmap(pixel.x() * scale);
+    }
+    max
+}
+
+/// 材质类型对应的颜色：内置材质使用固定的颜色，其他材质由名称的哈希得到色相
+pub fn material_color(name: &str) -> Color {
+    match name {
+        "lambertian" => Color::new(0.9, 0.6, 0.2),
+        "metal" => Color::new(0.2, 0.6, 1.0),
+        "dielectric" => Color::new(0.2, 0.9, 0.9),
+        "diffuse_light" => Color::new(1.0, 1.0, 1.0),
+        "isotropic" => Color::new(0.7, 0.3, 0.9),
+        _ => {
+            let mut hasher = Fnv64::new();
+            hasher.write_bytes(name.as_bytes());
+            hue_color((hasher.finish() >> 32) as f64 % 360.0)
+        }
+    }
+}
+
+/// 饱和度与明度取 1 的 HSV 颜色，`hue` 以度为单位
+fn hue_color(hue: f64) -> Color {
+    let h = hue / 60.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    Color::new(r, g, b)
+}
@@ -0,0 +1,62 @@
+use crate::rtweekend::degrees_to_radians;
+
+/// 物理相机的曝光参数
+///
+/// 胶片响应按 `t·S / (100·N²) · 2^EC` 缩放：f/1、1 秒、ISO 100、无补偿时为 1，
+/// 即与不设置曝光时的亮度一致。光圈每收小一档或快门减半，画面暗一档
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exposure {
+    pub f_number: f64,     // 光圈值 N
+    pub shutter_time: f64, // 快门时间（秒）
+    pub iso: f64,          // 感光度 S
+    pub compensation: f64, // 曝光补偿（EV）
+
+    pub drive_defocus: bool,     // 由光圈值计算 defocus_angle
+    pub drive_motion_blur: bool, // 由快门时间决定快门关闭时刻 shutter_open + shutter_time
+    pub sensor_height: f64,      // 传感器高度（毫米），与 vfov 一起决定焦距
+    pub units_per_meter: f64,    // 每米对应的场景单位数
+}
+
+impl Exposure {
+    pub fn new(f_number: f64, shutter_time: f64, iso: f64) -> Self {
+        Self {
+            f_number,
+            shutter_time,
+            iso,
+            compensation: 0.0,
+            drive_defocus: false,
+            drive_motion_blur: false,
+            sensor_height: 24.0,
+            units_per_meter: 1.0,
+        }
+    }
+
+    /// ISO 100 下的曝光值 EV100（已计入曝光补偿）
+    pub fn ev100(&self) -> f64 {
+        (self.f_number * self.f_number / self.shutter_time * 100.0 / self.iso).log2()
+            - self.compensation
+    }
+
+    /// 胶片响应的缩放系数
+    pub fn scale(&self) -> f64 {
+        2.0_f64.powf(-self.ev100())
+    }
+
+    /// 由视角和传感器高度得到的焦距（毫米）
+    pub fn focal_length(&self, vfov: f64) -> f64 {
+        0.5 * self.sensor_height / (degrees_to_radians(vfov) / 2.0).tan()
+    }
+
+    /// 光圈半径（场景单位）
+    pub fn aperture_radius(&self, vfov: f64) -> f64 {
+        let diameter_mm = self.focal_length(vfov) / self.f_number;
+        0.5 * diameter_mm / 1000.0 * self.units_per_meter
+    }
+
+    /// 在对焦距离处与光圈大小对应的 `defocus_angle`（度）
+    pub fn defocus_angle(&self, vfov: f64, focus_dist: f64) -> f64 {
+        2.0 * (self.aperture_radius(vfov) / focus_dist)
+            .atan()
+            .to_degrees()
+    }
+}
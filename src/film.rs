@@ -0,0 +1,63 @@
+use crate::color::{Color, write_color_to_string};
+use std::io::{self, Write};
+
+/// 渲染结果：行优先存储的线性颜色
+#[derive(Debug, Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "像素数与图像尺寸不符");
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    /// 以 P3 格式写出（含 gamma 校正）
+    pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+        for pixel in &self.pixels {
+            out.write_all(write_color_to_string(pixel).as_bytes())?;
+        }
+        out.flush()
+    }
+
+    /// 以 PPM 格式写到标准输出，完成后在标准错误上提示
+    pub fn write_stdout(&self) {
+        let stdout = io::stdout();
+        self.write_ppm(&mut io::BufWriter::new(stdout.lock()))
+            .unwrap();
+
+        eprint!("\rDone.                 \n");
+        io::stderr().flush().unwrap();
+    }
+
+    /// 左右拼接两张等高的图像
+    pub fn side_by_side(left: &Film, right: &Film) -> Film {
+        assert_eq!(left.height, right.height, "左右拼接要求高度相同");
+        let mut pixels = Vec::with_capacity(left.pixels.len() + right.pixels.len());
+        for y in 0..left.height {
+            pixels.extend_from_slice(&left.pixels[y * left.width..(y + 1) * left.width]);
+            pixels.extend_from_slice(&right.pixels[y * right.width..(y + 1) * right.width]);
+        }
+        Film::new(left.width + right.width, left.height, pixels)
+    }
+
+    /// 上下拼接两张等宽的图像
+    pub fn top_bottom(top: &Film, bottom: &Film) -> Film {
+        assert_eq!(top.width, bottom.width, "上下拼接要求宽度相同");
+        let mut pixels = top.pixels.clone();
+        pixels.extend_from_slice(&bottom.pixels);
+        Film::new(top.width, top.height + bottom.height, pixels)
+    }
+}
@@ -0,0 +1,591 @@
+use rayon::prelude::*;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::aabb::Aabb;
+use crate::bvh::{BvhOptions, BvhStats, INTERSECT_COST, SplitMethod, TRAVERSAL_COST};
+use crate::checkpoint::SceneHasher;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::lbvh;
+use crate::packet::{PacketHits, RayPacket, hit_each};
+use crate::ray::Ray;
+use crate::stats::count_node_visit;
+use crate::vec3::{Point3, Vec3};
+
+/// 遍历栈放在栈上的容量；自顶向下构建的树深不超过它，更深的树溢出到堆上
+const STACK_SIZE: usize = 64;
+
+/// 遍历栈：前 `N` 项放在栈上的数组中，超出部分放进 `Vec`，任意深度的树都能遍历
+pub(crate) struct TraversalStack<T: Copy, const N: usize> {
+    inline: [T; N],
+    len: usize,
+    spill: Vec<T>,
+}
+
+impl<T: Copy, const N: usize> TraversalStack<T, N> {
+    /// `fill` 只用于初始化数组，不会被弹出
+    pub(crate) fn new(fill: T) -> Self {
+        Self {
+            inline: [fill; N],
+            len: 0,
+            spill: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, value: T) {
+        if self.len < N {
+            self.inline[self.len] = value;
+            self.len += 1;
+        } else {
+            self.spill.push(value);
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        // 溢出部分总是最后压入的
+        if let Some(value) = self.spill.pop() {
+            return Some(value);
+        }
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.inline[self.len])
+    }
+}
+
+/// 线性化 BVH 的节点
+///
+/// 节点按深度优先顺序存放，内部节点的左子节点紧跟其后，`offset` 为右子节点下标；
+/// 叶节点的 `offset` 为第一个图元的下标，`count` 为图元数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlatNode {
+    pub bbox: Aabb,
+    pub offset: u32,
+    pub count: u32, // 0 表示内部节点
+    pub axis: u8,   // 内部节点的划分轴，遍历时据此先访问近侧子节点
+}
+
+impl FlatNode {
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+/// 按近侧子节点优先的顺序用栈遍历线性化节点，返回是否击中
+///
+/// 叶节点中的第 k 个图元由 `hit_primitive(k, 当前区间, rec)` 求交，击中后区间上限随之缩小
+pub fn traverse(
+    nodes: &[FlatNode],
+    r: &Ray,
+    ray_t: Interval,
+    rec: &mut HitRecord,
+    mut hit_primitive: impl FnMut(usize, Interval, &mut HitRecord) -> bool,
+) -> bool {
+    if nodes.is_empty() {
+        return false;
+    }
+
+    let origin = r.origin();
+    let direction = r.direction();
+    let inv_dir = Vec3::new(
+        1.0 / direction.x(),
+        1.0 / direction.y(),
+        1.0 / direction.z(),
+    );
+    let dir_is_neg = [inv_dir.x() < 0.0, inv_dir.y() < 0.0, inv_dir.z() < 0.0];
+
+    let mut hit_anything = false;
+    let mut closest_so_far = ray_t.max;
+
+    let mut stack = TraversalStack::<u32, STACK_SIZE>::new(0);
+    let mut current = 0usize;
+
+    loop {
+        let node = &nodes[current];
+        count_node_visit();
+        if node
+            .bbox
+            .hit_inv(origin, &inv_dir, Interval::new(ray_t.min, closest_so_far))
+        {
+            if node.is_leaf() {
+                let start = node.offset as usize;
+                for k in start..start + node.count as usize {
+                    if hit_primitive(k, Interval::new(ray_t.min, closest_so_far), rec) {
+                        hit_anything = true;
+                        closest_so_far = rec.t;
+                    }
+                }
+            } else {
+                // 先访问射线方向上较近的子节点，另一个压栈
+                let (near, far) = if dir_is_neg[node.axis as usize] {
+                    (node.offset as usize, current + 1)
+                } else {
+                    (current + 1, node.offset as usize)
+                };
+                stack.push(far as u32);
+                current = near;
+                continue;
+            }
+        }
+
+        match stack.pop() {
+            Some(next) => current = next as usize,
+            None => break,
+        }
+    }
+
+    hit_anything
+}
+
+/// 成束遍历：每个节点用四通道测试所有活动射线，只要有射线击中就继续向下，
+/// 叶节点中的第 k 个图元由 `hit_primitive(k, 击中该叶节点的射线, hits)` 求交
+///
+/// 近侧子节点按第一条射线的方向选择，调用方需保证射线束方向符号一致（`RayPacket::is_coherent`）
+pub fn traverse_packet(
+    nodes: &[FlatNode],
+    packet: &RayPacket,
+    active: u64,
+    hits: &mut PacketHits,
+    mut hit_primitive: impl FnMut(usize, u64, &mut PacketHits),
+) {
+    if nodes.is_empty() || active == 0 {
+        return;
+    }
+
+    let dir_is_neg = packet.direction_is_negative();
+
+    let mut stack = TraversalStack::<(u32, u64), STACK_SIZE>::new((0, 0));
+    let mut current = 0usize;
+    let mut mask = active;
+
+    loop {
+        let node = &nodes[current];
+        count_node_visit();
+        let node_mask = packet.hit_box(&node.bbox, mask, hits);
+        if node_mask != 0 {
+            if node.is_leaf() {
+                let start = node.offset as usize;
+                for k in start..start + node.count as usize {
+                    hit_primitive(k, node_mask, hits);
+                }
+            } else {
+                let (near, far) = if dir_is_neg[node.axis as usize] {
+                    (node.offset as usize, current + 1)
+                } else {
+                    (current + 1, node.offset as usize)
+                };
+                stack.push((far as u32, node_mask));
+                current = near;
+                mask = node_mask;
+                continue;
+            }
+        }
+
+        match stack.pop() {
+            Some((next, next_mask)) => (current, mask) = (next as usize, next_mask),
+            None => break,
+        }
+    }
+}
+
+/// 对一组包围盒构建线性化 BVH，返回节点、图元顺序和统计信息
+///
+/// 叶节点引用的是 `order` 中的位置：第 k 个图元为原数组中的 `order[k]`。
+/// 三种布局共用这里的划分，`BvhNode` 与 `WideBvh` 都由得到的节点转换而来
+pub fn build_nodes(boxes: &[Aabb], options: &BvhOptions) -> (Vec<FlatNode>, Vec<usize>, BvhStats) {
+    let start = Instant::now();
+    let (mut nodes, order, mut stats) = match options.split {
+        SplitMethod::Lbvh { treelet_rounds } => lbvh::build_nodes(boxes, options, treelet_rounds),
+        _ => build_top_down(boxes, options),
+    };
+    // 叶节点含多个图元时实际节点数远少于预留的 2n
+    nodes.shrink_to_fit();
+    stats.build_time = start.elapsed();
+
+    (nodes, order, stats)
+}
+
+/// 自顶向下逐层划分（中值或分桶 SAH）
+fn build_top_down(boxes: &[Aabb], options: &BvhOptions) -> (Vec<FlatNode>, Vec<usize>, BvhStats) {
+    let mut builder = Builder {
+        boxes,
+        centroids: boxes.iter().map(|b| b.centroid()).collect(),
+        options,
+        nodes: Vec::with_capacity(2 * boxes.len()),
+        order: Vec::with_capacity(boxes.len()),
+    };
+
+    let mut indices: Vec<usize> = (0..boxes.len()).collect();
+    let stats = if indices.is_empty() {
+        BvhStats::default()
+    } else {
+        builder.build(&mut indices, 0)
+    };
+
+    (builder.nodes, builder.order, stats)
+}
+
+struct Builder<'a> {
+    boxes: &'a [Aabb],
+    centroids: Vec<Point3>,
+    options: &'a BvhOptions,
+    nodes: Vec<FlatNode>,
+    order: Vec<usize>,
+}
+
+impl Builder<'_> {
+    /// 构建深度为 `depth` 的子树
+    ///
+    /// 剩余图元按中值划分也会使树深超过 `STACK_SIZE` 时改用中值划分，
+    /// 中值划分每层减半，保证叶节点深度不超过 `STACK_SIZE`，遍历栈不必溢出到堆上
+    fn build(&mut self, indices: &mut [usize], depth: usize) -> BvhStats {
+        let node_index = self.nodes.len();
+        let bbox = indices
+            .iter()
+            .fold(Aabb::EMPTY, |acc, &i| Aabb::from_aabbs(acc, self.boxes[i]));
+        self.nodes.push(FlatNode {
+            bbox,
+            offset: 0,
+            count: 0,
+            axis: 0,
+        });
+
+        let median_depth = indices.len().next_power_of_two().trailing_zeros() as usize;
+        let (mid, axis, split_cost) = if indices.len() == 1 {
+            (0, 0, f64::INFINITY)
+        } else if depth + median_depth >= STACK_SIZE {
+            self.median_split(indices, &bbox)
+        } else {
+            self.split(indices, &bbox)
+        };
+
+        let leaf_cost = INTERSECT_COST * indices.len() as f64;
+        if indices.len() == 1
+            || (indices.len() <= self.options.max_leaf_size && leaf_cost <= split_cost)
+        {
+            let node = &mut self.nodes[node_index];
+            node.offset = self.order.len() as u32;
+            node.count = indices.len() as u32;
+            self.order.extend_from_slice(indices);
+            return BvhStats::leaf(indices.len());
+        }
+
+        let (left_indices, right_indices) = indices.split_at_mut(mid);
+        let left_index = self.nodes.len();
+        let left_stats = self.build(left_indices, depth + 1);
+        let right_index = self.nodes.len();
+        let right_stats = self.build(right_indices, depth + 1);
+
+        let node = &mut self.nodes[node_index];
+        node.offset = right_index as u32;
+        node.axis = axis as u8;
+
+        BvhStats::interior(
+            &bbox,
+            (&self.nodes[left_index].bbox, &left_stats),
+            (&self.nodes[right_index].bbox, &right_stats),
+        )
+    }
+
+    /// 重排 `indices`，返回 (分界位置, 划分轴, 划分代价)
+    fn split(&self, indices: &mut [usize], bbox: &Aabb) -> (usize, usize, f64) {
+        if let SplitMethod::Sah { bins } = self.options.split
+            && let Some(split) = self.sah_split(indices, bbox, bins)
+        {
+            return split;
+        }
+
+        self.median_split(indices, bbox)
+    }
+
+    /// 沿最长轴排序后从中间分开，不估计代价
+    fn median_split(&self, indices: &mut [usize], bbox: &Aabb) -> (usize, usize, f64) {
+        let axis = bbox.longest_axis();
+        indices.sort_by(|&a, &b| {
+            self.boxes[a]
+                .axis_interval(axis)
+                .min
+                .partial_cmp(&self.boxes[b].axis_interval(axis).min)
+                .unwrap()
+        });
+        (indices.len() / 2, axis, f64::INFINITY)
+    }
+
+    fn sah_split(
+        &self,
+        indices: &mut [usize],
+        bbox: &Aabb,
+        bins: usize,
+    ) -> Option<(usize, usize, f64)> {
+        let mut min = self.centroids[indices[0]];
+        let mut max = min;
+        for &i in indices.iter() {
+            min = min.min(self.centroids[i]);
+            max = max.max(self.centroids[i]);
+        }
+
+        let area = bbox.surface_area();
+        let mut best: Option<(usize, usize, f64)> = None; // (axis, 分界桶, 代价)
+
+        for axis in 0..3 {
+            let extent = Interval::new(min[axis], max[axis]);
+            if extent.size() <= 0.0 {
+                continue;
+            }
+
+            let mut counts = vec![0usize; bins];
+            let mut boxes = vec![Aabb::EMPTY; bins];
+            for &i in indices.iter() {
+                let b = bin_index(self.centroids[i][axis], &extent, bins);
+                counts[b] += 1;
+                boxes[b] = Aabb::from_aabbs(boxes[b], self.boxes[i]);
+            }
+
+            let mut right_area = vec![0.0; bins];
+            let mut right_count = vec![0usize; bins];
+            let mut acc_box = Aabb::EMPTY;
+            let mut acc_count = 0;
+            for b in (1..bins).rev() {
+                acc_box = Aabb::from_aabbs(acc_box, boxes[b]);
+                acc_count += counts[b];
+                right_area[b] = acc_box.surface_area();
+                right_count[b] = acc_count;
+            }
+
+            let mut left_box = Aabb::EMPTY;
+            let mut left_count = 0;
+            for b in 1..bins {
+                left_box = Aabb::from_aabbs(left_box, boxes[b - 1]);
+                left_count += counts[b - 1];
+                if left_count == 0 || right_count[b] == 0 {
+                    continue;
+                }
+
+                let cost = TRAVERSAL_COST
+                    + INTERSECT_COST
+                        * (left_box.surface_area() * left_count as f64
+                            + right_area[b] * right_count[b] as f64)
+                        / area;
+                if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    best = Some((axis, b, cost));
+                }
+            }
+        }
+
+        let (axis, split_bin, cost) = best?;
+        let extent = Interval::new(min[axis], max[axis]);
+        let mid = partition_in_place(indices, |&i| {
+            bin_index(self.centroids[i][axis], &extent, bins) < split_bin
+        });
+
+        Some((mid, axis, cost))
+    }
+}
+
+fn bin_index(value: f64, extent: &Interval, bins: usize) -> usize {
+    let t = (value - extent.min) / extent.size();
+    ((t * bins as f64) as usize).min(bins - 1)
+}
+
+/// 把满足 `pred` 的元素移到前面，返回满足条件的个数
+fn partition_in_place<T>(items: &mut [T], mut pred: impl FnMut(&T) -> bool) -> usize {
+    let mut first = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(first, i);
+            first += 1;
+        }
+    }
+    first
+}
+
+/// 线性化的 BVH：节点存放在连续的数组中，图元按叶节点顺序重排
+///
+/// 图元随时间运动时可用 `refit` 逐帧更新包围盒而不必重建
+#[derive(Debug, Clone)]
+pub struct FlatBvh {
+    nodes: Vec<FlatNode>,
+    objects: Vec<Arc<dyn Hittable + Send + Sync>>,
+    stats: BvhStats,
+    options: BvhOptions,
+    /// 衡量重拟合后树质量的基准 SAH 代价
+    ///
+    /// 按某一帧的包围盒重建时即为构建时的代价；按整段运动的包围盒构建时两者不可比，
+    /// 为 `None`，由第一次重拟合记录
+    build_cost: Option<f64>,
+}
+
+impl FlatBvh {
+    pub fn new(list: &HittableList, options: BvhOptions) -> Self {
+        Self::from_objects(list.objects.clone(), options)
+    }
+
+    pub fn from_objects(
+        objects: Vec<Arc<dyn Hittable + Send + Sync>>,
+        options: BvhOptions,
+    ) -> Self {
+        let boxes: Vec<Aabb> = objects.iter().map(|o| o.bounding_box()).collect();
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            objects,
+            stats: BvhStats::default(),
+            options,
+            build_cost: None,
+        };
+        bvh.rebuild_with(&boxes);
+        bvh.build_cost = None;
+        bvh
+    }
+
+    /// 由已构建好的节点组装，`objects` 须已按叶节点顺序排列
+    pub fn from_nodes(
+        nodes: Vec<FlatNode>,
+        objects: Vec<Arc<dyn Hittable + Send + Sync>>,
+        stats: BvhStats,
+        options: BvhOptions,
+    ) -> Self {
+        Self {
+            nodes,
+            objects,
+            stats,
+            options,
+            build_cost: None,
+        }
+    }
+
+    /// 当前树的统计信息，重拟合后 `sah_cost` 随之更新
+    pub fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
+
+    /// 树的退化程度：当前 SAH 代价与基准之比，刚构建完或刚记录基准时为 1
+    pub fn degradation(&self) -> f64 {
+        match self.build_cost {
+            Some(cost) if cost > 0.0 => self.stats.sah_cost / cost,
+            _ => 1.0,
+        }
+    }
+
+    /// 按图元在 `time` 内的包围盒自底向上更新各节点，树的拓扑不变
+    ///
+    /// 图元自身支持重拟合时（如嵌套的 `FlatBvh`）先更新图元。构建后的第一次重拟合只记录基准代价，
+    /// 之后退化程度超过 `BvhOptions::rebuild_threshold` 时按新包围盒完全重建。返回是否进行了重建
+    pub fn refit(&mut self, time: Interval) -> bool {
+        self.objects.par_iter_mut().for_each(|object| {
+            if let Some(refitted) = object.refitted(time) {
+                *object = refitted;
+            }
+        });
+        let boxes = self.object_boxes(time);
+
+        // 子节点的下标总是大于父节点，倒序遍历即为自底向上
+        let mut stats = vec![BvhStats::default(); self.nodes.len()];
+        for i in (0..self.nodes.len()).rev() {
+            let node = self.nodes[i];
+            if node.is_leaf() {
+                let start = node.offset as usize;
+                let count = node.count as usize;
+                self.nodes[i].bbox = boxes[start..start + count]
+                    .iter()
+                    .fold(Aabb::EMPTY, |acc, &b| Aabb::from_aabbs(acc, b));
+                stats[i] = BvhStats::leaf(count);
+            } else {
+                let left = &self.nodes[i + 1].bbox;
+                let right = &self.nodes[node.offset as usize].bbox;
+                let bbox = Aabb::from_aabbs(*left, *right);
+                stats[i] = BvhStats::interior(
+                    &bbox,
+                    (left, &stats[i + 1]),
+                    (right, &stats[node.offset as usize]),
+                );
+                self.nodes[i].bbox = bbox;
+            }
+        }
+        self.stats = BvhStats {
+            build_time: self.stats.build_time,
+            ..stats.first().copied().unwrap_or_default()
+        };
+
+        if self.build_cost.is_none() {
+            self.build_cost = Some(self.stats.sah_cost);
+            return false;
+        }
+        if self.degradation() > self.options.rebuild_threshold {
+            self.rebuild_with(&boxes);
+            return true;
+        }
+        false
+    }
+
+    /// 按图元在 `time` 内的包围盒完全重建
+    pub fn rebuild(&mut self, time: Interval) {
+        let boxes = self.object_boxes(time);
+        self.rebuild_with(&boxes);
+    }
+
+    /// 以 `boxes[i]` 作为第 i 个图元的包围盒重建
+    fn rebuild_with(&mut self, boxes: &[Aabb]) {
+        let (nodes, order, stats) = build_nodes(boxes, &self.options);
+        self.objects = order.iter().map(|&i| self.objects[i].clone()).collect();
+        self.nodes = nodes;
+        self.stats = stats;
+        self.build_cost = Some(stats.sah_cost);
+    }
+
+    fn object_boxes(&self, time: Interval) -> Vec<Aabb> {
+        self.objects
+            .par_iter()
+            .map(|object| object.bounding_box_during(time))
+            .collect()
+    }
+
+    pub fn nodes(&self) -> &[FlatNode] {
+        &self.nodes
+    }
+}
+
+impl Hittable for FlatBvh {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        traverse(&self.nodes, r, ray_t, rec, |k, ray_t, rec| {
+            self.objects[k].hit(r, ray_t, rec)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |node| node.bbox)
+    }
+
+    /// 按叶节点顺序哈希图元，树的结构与构建统计不影响渲染结果
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("bvh");
+        hasher.write_u64(self.objects.len() as u64);
+        for object in &self.objects {
+            object.hash_content(hasher);
+        }
+    }
+
+    /// 成束遍历（见 `traverse_packet`），方向符号不一致的射线束退回逐条遍历
+    fn hit_packet(&self, packet: &RayPacket, active: u64, hits: &mut PacketHits) {
+        if !packet.is_coherent() {
+            hit_each(self, packet, active, hits);
+            return;
+        }
+
+        traverse_packet(&self.nodes, packet, active, hits, |k, mask, hits| {
+            self.objects[k].hit_packet(packet, mask, hits)
+        });
+    }
+
+    fn refitted(&self, time: Interval) -> Option<Arc<dyn Hittable + Send + Sync>> {
+        let mut bvh = self.clone();
+        bvh.refit(time);
+        Some(Arc::new(bvh))
+    }
+
+    fn bvh_stats(&self) -> Option<BvhStats> {
+        Some(self.stats)
+    }
+}
@@ -3,8 +3,11 @@ use std::ops::Neg;
 use std::sync::Arc;
 
 use crate::aabb::Aabb;
+use crate::bvh::BvhStats;
+use crate::checkpoint::SceneHasher;
 use crate::interval::Interval;
 use crate::material::MaterialPtr;
+use crate::packet::{PacketHits, RayPacket, hit_each};
 use crate::ray::Ray;
 use crate::rtweekend::INFINITY;
 use crate::vec3::{Point3, Vec3, dot};
@@ -19,9 +22,14 @@ pub struct HitRecord {
     pub u: f64,                   // 纹理坐标u
     pub v: f64,
     pub front_face: bool,
+    pub geometric_normal: Vec3, // 几何法向，与 normal 同在射线一侧；normal 是插值得到的着色法向时两者不同
 }
 
+/// 新射线起点沿几何法向离开表面的距离
+const SPAWN_OFFSET: f64 = 1e-4;
+
 impl HitRecord {
+    /// 设置几何法向，同时作为着色法向
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) {
         // 法向量向外为true，向内为false
         self.front_face = dot(r.direction(), &outward_normal) < 0.0;
@@ -30,6 +38,35 @@ impl HitRecord {
         } else {
             outward_normal.neg()
         };
+        self.geometric_normal = self.normal;
+    }
+
+    /// 用插值得到的着色法向替换 `normal`，在 `set_face_normal` 之后调用
+    ///
+    /// 着色法向翻到几何法向所在的一侧；`front_face` 与 `geometric_normal` 保持不变
+    pub fn set_shading_normal(&mut self, shading_normal: Vec3) {
+        self.normal = if dot(&shading_normal, &self.geometric_normal) < 0.0 {
+            shading_normal.neg()
+        } else {
+            shading_normal
+        };
+    }
+
+    /// 从交点出发、方向为 `direction` 的新射线的起点
+    ///
+    /// 沿几何法向推到 `direction` 所在的一侧，避免新射线再次击中同一表面
+    pub fn spawn_origin(&self, direction: &Vec3) -> Point3 {
+        let offset = SPAWN_OFFSET * self.geometric_normal;
+        if dot(direction, &self.geometric_normal) < 0.0 {
+            self.p - offset
+        } else {
+            self.p + offset
+        }
+    }
+
+    /// `direction` 是否指向射线来的一侧（反射而不是透射）
+    pub fn is_above(&self, direction: &Vec3) -> bool {
+        dot(direction, &self.geometric_normal) > 0.0
     }
 }
 
@@ -48,6 +85,40 @@ pub trait Hittable: Debug {
     // fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64, rec: &mut HitRecord) -> bool;
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
     fn bounding_box(&self) -> Aabb;
+
+    /// 一次求交一束射线，只处理 `active` 中的射线
+    ///
+    /// 第 k 条射线在 (hits.t_min, hits.t_max[k]) 内击中更近的交点时写入 `hits.recs[k]`
+    /// 并调用 `hits.record(k)`。默认逐条调用 `hit`
+    fn hit_packet(&self, packet: &RayPacket, active: u64, hits: &mut PacketHits) {
+        hit_each(self, packet, active, hits);
+    }
+
+    /// 射线时间落在 `time` 内时的包围盒，可比 `bounding_box` 更紧；默认与其相同
+    fn bounding_box_during(&self, _time: Interval) -> Aabb {
+        self.bounding_box()
+    }
+
+    /// 按 `time` 内的包围盒更新加速结构，返回更新后的物体；无需更新时返回 `None`
+    ///
+    /// 渲染动画序列时每帧以该帧的快门区间调用一次
+    fn refitted(&self, _time: Interval) -> Option<Arc<dyn Hittable + Send + Sync>> {
+        None
+    }
+
+    /// 把影响渲染结果的内容（几何、变换与材质参数）写入 `hasher`，用于判断断点是否仍然有效
+    ///
+    /// 默认哈希 `Debug` 输出；含构建耗时等每次运行都不同的字段或大量数据的类型应当覆盖，
+    /// 只写入几何参数，材质通过 `SceneHasher::write_material` 写入
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_debug(self);
+    }
+
+    /// 物体自身（或其中最大的）BVH 的统计信息，不含 BVH 时为 `None`
+    fn bvh_stats(&self) -> Option<BvhStats> {
+        None
+    }
+
     fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
         0.0
     }
@@ -91,6 +162,16 @@ impl Hittable for Translate {
     fn bounding_box(&self) -> Aabb {
         self.bbox
     }
+
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("translate");
+        self.object.hash_content(hasher);
+        hasher.write_vec3(&self.offset);
+    }
+
+    fn bounding_box_during(&self, time: Interval) -> Aabb {
+        self.object.bounding_box_during(time) + self.offset
+    }
 }
 
 #[derive(Debug)]
@@ -168,14 +249,17 @@ impl Hittable for RotateY {
             -self.sin_theta * rec.p.x() + self.cos_theta * rec.p.z(),
         );
 
-        let normal = Vec3::new(
-            self.cos_theta * rec.normal.x() + self.sin_theta * rec.normal.z(),
-            rec.normal.y(),
-            -self.sin_theta * rec.normal.x() + self.cos_theta * rec.normal.z(),
-        );
+        let rotate = |n: &Vec3| {
+            Vec3::new(
+                self.cos_theta * n.x() + self.sin_theta * n.z(),
+                n.y(),
+                -self.sin_theta * n.x() + self.cos_theta * n.z(),
+            )
+        };
 
         rec.p = p;
-        rec.normal = normal;
+        rec.normal = rotate(&rec.normal);
+        rec.geometric_normal = rotate(&rec.geometric_normal);
         // rec.set_face_normal(r, normal);
 
         true
@@ -184,4 +268,11 @@ impl Hittable for RotateY {
     fn bounding_box(&self) -> Aabb {
         self.bbox
     }
+
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("rotate_y");
+        self.object.hash_content(hasher);
+        hasher.write_f64(self.sin_theta);
+        hasher.write_f64(self.cos_theta);
+    }
 }
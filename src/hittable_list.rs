@@ -1,6 +1,9 @@
 use crate::aabb::Aabb;
+use crate::bvh::BvhStats;
+use crate::checkpoint::SceneHasher;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
+use crate::packet::{PacketHits, RayPacket};
 use crate::ray::Ray;
 use crate::rtweekend::random_int;
 use std::sync::Arc;
@@ -64,6 +67,51 @@ impl Hittable for HittableList {
         self.bbox
     }
 
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("list");
+        hasher.write_u64(self.objects.len() as u64);
+        for object in &self.objects {
+            object.hash_content(hasher);
+        }
+    }
+
+    fn bvh_stats(&self) -> Option<BvhStats> {
+        self.objects
+            .iter()
+            .filter_map(|object| object.bvh_stats())
+            .max_by_key(|stats| stats.primitives)
+    }
+
+    fn hit_packet(&self, packet: &RayPacket, active: u64, hits: &mut PacketHits) {
+        for object in &self.objects {
+            object.hit_packet(packet, active, hits);
+        }
+    }
+
+    fn bounding_box_during(&self, time: Interval) -> Aabb {
+        self.objects.iter().fold(Aabb::EMPTY, |bbox, object| {
+            Aabb::from_aabbs(bbox, object.bounding_box_during(time))
+        })
+    }
+
+    /// 逐个更新列表中的物体，有任一物体更新时返回新的列表
+    fn refitted(&self, time: Interval) -> Option<Arc<dyn Hittable + Send + Sync>> {
+        let refitted: Vec<_> = self
+            .objects
+            .iter()
+            .map(|object| object.refitted(time))
+            .collect();
+        if refitted.iter().all(Option::is_none) {
+            return None;
+        }
+
+        let mut list = HittableList::new();
+        for (object, refitted) in self.objects.iter().zip(refitted) {
+            list.add(refitted.unwrap_or_else(|| object.clone()));
+        }
+        Some(Arc::new(list))
+    }
+
     fn pdf_value(&self, origin: &crate::vec3::Point3, direction: &crate::vec3::Vec3) -> f64 {
         let weight = 1.0 / self.objects.len() as f64;
         let mut sum = 0.0;
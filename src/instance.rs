@@ -0,0 +1,127 @@
+use glam::{DAffine3, DMat3, DVec3};
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::checkpoint::SceneHasher;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::MaterialPtr;
+use crate::ray::Ray;
+use crate::rtweekend::INFINITY;
+use crate::vec3::{Point3, Vec3, unit_vector};
+
+/// 对共享几何体（底层 BVH）的一次引用，带有仿射变换和可选的材质覆盖
+///
+/// 同一个 `Arc<Mesh>` 可以被任意多个实例引用，几何数据与底层 BVH 只保存一份；
+/// 把实例放进 `HittableList` 再用 `build_bvh` 构建顶层 BVH 即可
+#[derive(Debug, Clone)]
+pub struct Instance {
+    object: Arc<dyn Hittable + Send + Sync>,
+    transform: DAffine3,
+    inverse: DAffine3,
+    normal_matrix: DMat3, // 线性部分的逆转置，用于变换法向
+    material: Option<MaterialPtr>,
+    bbox: Aabb,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, transform: DAffine3) -> Self {
+        let inverse = transform.inverse();
+        let normal_matrix = transform.matrix3.inverse().transpose();
+        let bbox = transform_bbox(&object.bounding_box(), &transform);
+
+        Self {
+            object,
+            transform,
+            inverse,
+            normal_matrix,
+            material: None,
+            bbox,
+        }
+    }
+
+    /// 用 `material` 替换被引用几何体自身的材质
+    pub fn with_material(mut self, material: MaterialPtr) -> Self {
+        self.material = Some(material);
+        self
+    }
+
+    pub fn transform(&self) -> &DAffine3 {
+        &self.transform
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        // 方向不归一化，物体空间与世界空间的射线参数 t 相同
+        let origin = self.inverse.transform_point3(to_dvec3(r.origin()));
+        let direction = self.inverse.transform_vector3(to_dvec3(r.direction()));
+        let local_r =
+            Ray::with_origin_dir_time(from_dvec3(origin), from_dvec3(direction), r.time());
+
+        if !self.object.hit(&local_r, ray_t, rec) {
+            return false;
+        }
+
+        rec.p = from_dvec3(self.transform.transform_point3(to_dvec3(&rec.p)));
+        rec.normal = unit_vector(from_dvec3(self.normal_matrix * to_dvec3(&rec.normal)));
+        rec.geometric_normal = unit_vector(from_dvec3(
+            self.normal_matrix * to_dvec3(&rec.geometric_normal),
+        ));
+        if let Some(material) = &self.material {
+            rec.mat = Some(material.clone());
+        }
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("instance");
+        self.object.hash_content(hasher);
+        for x in self.transform.to_cols_array() {
+            hasher.write_f64(x);
+        }
+        match &self.material {
+            Some(material) => hasher.write_material(material.as_ref()),
+            None => hasher.write_u64(0),
+        }
+    }
+
+    fn bounding_box_during(&self, time: Interval) -> Aabb {
+        transform_bbox(&self.object.bounding_box_during(time), &self.transform)
+    }
+}
+
+/// 变换包围盒的 8 个角点，取其包围盒
+fn transform_bbox(bbox: &Aabb, transform: &DAffine3) -> Aabb {
+    let mut min = Point3::new(INFINITY, INFINITY, INFINITY);
+    let mut max = Point3::new(-INFINITY, -INFINITY, -INFINITY);
+
+    for i in 0..2 {
+        for j in 0..2 {
+            for k in 0..2 {
+                let x = if i == 0 { bbox.x.min } else { bbox.x.max };
+                let y = if j == 0 { bbox.y.min } else { bbox.y.max };
+                let z = if k == 0 { bbox.z.min } else { bbox.z.max };
+
+                let tester = from_dvec3(transform.transform_point3(DVec3::new(x, y, z)));
+                min = min.min(tester);
+                max = max.max(tester);
+            }
+        }
+    }
+
+    Aabb::from_points(min, max)
+}
+
+pub(crate) fn to_dvec3(v: &Vec3) -> DVec3 {
+    DVec3::new(v.x(), v.y(), v.z())
+}
+
+pub(crate) fn from_dvec3(v: DVec3) -> Vec3 {
+    Vec3::new(v.x, v.y, v.z)
+}
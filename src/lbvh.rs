@@ -0,0 +1,496 @@
+use rayon::join;
+use rayon::prelude::*;
+
+use crate::aabb::Aabb;
+use crate::bvh::{BvhOptions, BvhStats, INTERSECT_COST, TRAVERSAL_COST};
+use crate::flat_bvh::FlatNode;
+
+/// 每个轴的 Morton 编码位数，三个轴共 63 位
+const MORTON_BITS: u32 = 21;
+
+/// 基数排序每轮处理的位数
+const RADIX_BITS: u32 = 8;
+const RADIX: usize = 1 << RADIX_BITS;
+
+/// 元素少于此数时改用比较排序
+const SORT_CUTOFF: usize = 256;
+
+/// 子问题小于此规模时不再并行
+const PARALLEL_CUTOFF: usize = 4096;
+
+/// treelet 的叶节点数，子集数为 2^TREELET_SIZE
+const TREELET_SIZE: usize = 7;
+
+/// 用 Morton 编码构建线性 BVH（LBVH），返回值与 `flat_bvh::build_nodes` 相同
+///
+/// 1. 把图元中心量化到 2^21 的网格上并交错成 63 位 Morton 编码
+/// 2. 并行基数排序，使空间上相邻的图元在数组中也相邻
+/// 3. 按相邻编码最高的不同位自顶向下划分，左右子树并行生成
+/// 4. 可选：做 `treelet_rounds` 轮 treelet 重组（Karras & Aila 2013），以 SAH 代价为准
+///    重新排列每个节点下 7 个子树的拓扑
+/// 5. 按 SAH 代价把不超过 `max_leaf_size` 个图元的子树合并为叶节点，输出为 `FlatNode`
+pub fn build_nodes(
+    boxes: &[Aabb],
+    options: &BvhOptions,
+    treelet_rounds: usize,
+) -> (Vec<FlatNode>, Vec<usize>, BvhStats) {
+    if boxes.is_empty() {
+        return (Vec::new(), Vec::new(), BvhStats::default());
+    }
+
+    let mut keys = morton_codes(boxes);
+    let mut scratch = vec![(0, 0); keys.len()];
+    radix_sort(&mut keys, &mut scratch, 64 - RADIX_BITS as i32);
+    drop(scratch);
+
+    let order: Vec<usize> = keys.iter().map(|&(_, i)| i as usize).collect();
+    let codes: Vec<u64> = keys.into_iter().map(|(code, _)| code).collect();
+
+    let mut nodes = vec![BuildNode::leaf(Aabb::EMPTY, 0); 2 * boxes.len() - 1];
+    emit(&codes, 0, &order, boxes, &mut nodes, 0);
+
+    for _ in 0..treelet_rounds {
+        optimize_treelets(&mut nodes, 0);
+        // 重组打乱了子树在数组中的位置，重新按先序排列，下一轮才能再按区间划分
+        nodes = reorder(&nodes);
+    }
+
+    let mut output = Output {
+        nodes: Vec::with_capacity(nodes.len()),
+        order: Vec::with_capacity(order.len()),
+        tree: &nodes,
+        sorted: &order,
+        max_leaf_size: options.max_leaf_size,
+    };
+    let stats = output.emit(0);
+
+    (output.nodes, output.order, stats)
+}
+
+/// 构建过程中的二叉树节点，每个叶节点恰好一个图元
+///
+/// 先序排列时以 `i` 为根、含 `count` 个图元的子树正好占据 `[i, i + 2 * count - 1)`
+#[derive(Debug, Clone, Copy)]
+struct BuildNode {
+    bbox: Aabb,
+    cost: f64,          // 未按根节点面积归一化的 SAH 代价
+    children: [u32; 2], // 内部节点的左右子节点（绝对下标）
+    count: u32,         // 子树中的图元数，1 表示叶节点
+    start: u32,         // 叶节点图元在排序后数组中的位置
+}
+
+impl BuildNode {
+    fn leaf(bbox: Aabb, start: usize) -> Self {
+        Self {
+            bbox,
+            cost: INTERSECT_COST * bbox.surface_area(),
+            children: [0; 2],
+            count: 1,
+            start: start as u32,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.count == 1
+    }
+}
+
+/// 计算各图元中心的 Morton 编码，返回 (编码, 图元下标)
+fn morton_codes(boxes: &[Aabb]) -> Vec<(u64, u32)> {
+    let bounds = boxes.par_iter().map(|b| b.centroid()).fold(
+        || Aabb::EMPTY,
+        |acc, c| Aabb::from_aabbs(acc, Aabb::from_points(c, c)),
+    );
+    let bounds = bounds.reduce(|| Aabb::EMPTY, Aabb::from_aabbs);
+
+    let scale = (1u64 << MORTON_BITS) as f64;
+    let quantize = |value: f64, axis: usize| {
+        let extent = bounds.axis_interval(axis);
+        if extent.size() <= 0.0 {
+            return 0;
+        }
+        let t = (value - extent.min) / extent.size();
+        ((t * scale) as u64).min((1 << MORTON_BITS) - 1)
+    };
+
+    boxes
+        .par_iter()
+        .enumerate()
+        .map(|(i, b)| {
+            let c = b.centroid();
+            let code = (spread_bits(quantize(c.x(), 0)) << 2)
+                | (spread_bits(quantize(c.y(), 1)) << 1)
+                | spread_bits(quantize(c.z(), 2));
+            (code, i as u32)
+        })
+        .collect()
+}
+
+/// 把 21 位整数的各位分散到每三位的最低位上
+fn spread_bits(v: u64) -> u64 {
+    let mut x = v & 0x1f_ffff;
+    x = (x | x << 32) & 0x001f_0000_0000_ffff;
+    x = (x | x << 16) & 0x001f_0000_ff00_00ff;
+    x = (x | x << 8) & 0x100f_00f0_0f00_f00f;
+    x = (x | x << 4) & 0x10c3_0c30_c30c_30c3;
+    x = (x | x << 2) & 0x1249_2492_4924_9249;
+    x
+}
+
+/// 从高位开始的基数排序（MSD），每轮按 `shift` 起的 8 位分桶，各桶并行递归
+fn radix_sort(keys: &mut [(u64, u32)], scratch: &mut [(u64, u32)], shift: i32) {
+    if keys.len() <= SORT_CUTOFF || shift < 0 {
+        keys.sort_unstable_by_key(|&(code, _)| code);
+        return;
+    }
+
+    let digit = |code: u64| ((code >> shift) as usize) & (RADIX - 1);
+    let counts = if keys.len() >= PARALLEL_CUTOFF {
+        keys.par_chunks(PARALLEL_CUTOFF)
+            .map(|chunk| {
+                let mut counts = [0usize; RADIX];
+                chunk.iter().for_each(|&(code, _)| counts[digit(code)] += 1);
+                counts
+            })
+            .reduce(
+                || [0usize; RADIX],
+                |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                    a
+                },
+            )
+    } else {
+        let mut counts = [0usize; RADIX];
+        keys.iter().for_each(|&(code, _)| counts[digit(code)] += 1);
+        counts
+    };
+
+    let mut offsets = [0usize; RADIX];
+    for d in 1..RADIX {
+        offsets[d] = offsets[d - 1] + counts[d - 1];
+    }
+    let mut next = offsets;
+    for &key in keys.iter() {
+        let d = digit(key.0);
+        scratch[next[d]] = key;
+        next[d] += 1;
+    }
+    keys.copy_from_slice(scratch);
+
+    // 把两个数组切成对应的桶，并行处理
+    let mut buckets = Vec::with_capacity(RADIX);
+    let (mut rest_keys, mut rest_scratch) = (keys, scratch);
+    for &count in &counts {
+        let (k, rk) = rest_keys.split_at_mut(count);
+        let (s, rs) = rest_scratch.split_at_mut(count);
+        if count > 1 {
+            buckets.push((k, s));
+        }
+        rest_keys = rk;
+        rest_scratch = rs;
+    }
+    buckets
+        .into_par_iter()
+        .for_each(|(k, s)| radix_sort(k, s, shift - RADIX_BITS as i32));
+}
+
+/// 为排序后第 `start` 起的图元（编码为 `codes`）生成子树，写入 `nodes`
+///
+/// `nodes` 为该子树在先序数组中的区间，`base` 为其第一个元素的绝对下标
+fn emit(
+    codes: &[u64],
+    start: usize,
+    order: &[usize],
+    boxes: &[Aabb],
+    nodes: &mut [BuildNode],
+    base: usize,
+) {
+    if codes.len() == 1 {
+        nodes[0] = BuildNode::leaf(boxes[order[start]], start);
+        return;
+    }
+
+    // 在首尾编码最高的不同位处划分；编码全部相同时从中间分开
+    let first = codes[0];
+    let last = codes[codes.len() - 1];
+    let mid = if first == last {
+        codes.len() / 2
+    } else {
+        let bit = 63 - (first ^ last).leading_zeros();
+        codes.partition_point(|&code| code & (1 << bit) == 0)
+    };
+
+    let (node, children) = nodes.split_first_mut().unwrap();
+    let (left_nodes, right_nodes) = children.split_at_mut(2 * mid - 1);
+    let (left_codes, right_codes) = codes.split_at(mid);
+    let left_base = base + 1;
+    let right_base = base + 2 * mid;
+
+    if codes.len() >= PARALLEL_CUTOFF {
+        join(
+            || emit(left_codes, start, order, boxes, left_nodes, left_base),
+            || {
+                emit(
+                    right_codes,
+                    start + mid,
+                    order,
+                    boxes,
+                    right_nodes,
+                    right_base,
+                )
+            },
+        );
+    } else {
+        emit(left_codes, start, order, boxes, left_nodes, left_base);
+        emit(
+            right_codes,
+            start + mid,
+            order,
+            boxes,
+            right_nodes,
+            right_base,
+        );
+    }
+
+    let (left, right) = (&left_nodes[0], &right_nodes[0]);
+    let bbox = Aabb::from_aabbs(left.bbox, right.bbox);
+    *node = BuildNode {
+        bbox,
+        cost: TRAVERSAL_COST * bbox.surface_area() + left.cost + right.cost,
+        children: [left_base as u32, right_base as u32],
+        count: codes.len() as u32,
+        start: 0,
+    };
+}
+
+/// 自底向上对以 `nodes[0]` 为根的子树做一轮 treelet 重组，左右子树并行处理
+///
+/// `nodes` 为先序排列的子树区间，`base` 为其第一个元素的绝对下标
+fn optimize_treelets(nodes: &mut [BuildNode], base: usize) {
+    let root = nodes[0];
+    if root.is_leaf() {
+        return;
+    }
+
+    let left_count = nodes[1].count as usize;
+    let (_, children) = nodes.split_first_mut().unwrap();
+    let (left_nodes, right_nodes) = children.split_at_mut(2 * left_count - 1);
+    if root.count as usize >= PARALLEL_CUTOFF {
+        join(
+            || optimize_treelets(left_nodes, base + 1),
+            || optimize_treelets(right_nodes, base + 2 * left_count),
+        );
+    } else {
+        optimize_treelets(left_nodes, base + 1);
+        optimize_treelets(right_nodes, base + 2 * left_count);
+    }
+
+    if root.count as usize >= TREELET_SIZE {
+        restructure(nodes, base);
+    }
+}
+
+/// 重组以 `nodes[0]` 为根的 treelet
+///
+/// 从根的两个子节点开始，反复展开表面积最大的内部节点，得到 7 个 treelet 叶节点；
+/// 再用动态规划求出这些叶节点 SAH 代价最小的二叉树，复用原有的内部节点写回
+fn restructure(nodes: &mut [BuildNode], base: usize) {
+    let local = |i: u32| i as usize - base;
+
+    let root = nodes[0];
+    let mut leaves: Vec<u32> = root.children.to_vec();
+    let mut interiors: Vec<u32> = vec![base as u32];
+    while leaves.len() < TREELET_SIZE {
+        let expand = leaves
+            .iter()
+            .enumerate()
+            .filter(|&(_, &i)| !nodes[local(i)].is_leaf())
+            .max_by(|&(_, &a), &(_, &b)| {
+                nodes[local(a)]
+                    .bbox
+                    .surface_area()
+                    .total_cmp(&nodes[local(b)].bbox.surface_area())
+            })
+            .map(|(k, _)| k);
+        let Some(k) = expand else {
+            break;
+        };
+        let i = leaves.swap_remove(k);
+        interiors.push(i);
+        leaves.extend_from_slice(&nodes[local(i)].children);
+    }
+    if leaves.len() < 3 {
+        return;
+    }
+
+    // 对每个叶节点子集求包围盒与最优代价，best_split[s] 为取得最优时的左子集
+    let subsets = 1usize << leaves.len();
+    let mut bbox = [Aabb::EMPTY; 1 << TREELET_SIZE];
+    let mut cost = [f64::INFINITY; 1 << TREELET_SIZE];
+    let mut best_split = [0usize; 1 << TREELET_SIZE];
+    for (k, &leaf) in leaves.iter().enumerate() {
+        bbox[1 << k] = nodes[local(leaf)].bbox;
+        cost[1 << k] = nodes[local(leaf)].cost;
+    }
+    for s in 1..subsets {
+        if s.count_ones() < 2 {
+            continue;
+        }
+        let low = s & s.wrapping_neg();
+        bbox[s] = Aabb::from_aabbs(bbox[s ^ low], bbox[low]);
+
+        // 左侧总是包含最低位，避免左右对称的重复划分：枚举其余位的真子集
+        let rest = s ^ low;
+        let mut best = f64::INFINITY;
+        let mut q = (rest - 1) & rest;
+        loop {
+            let p = q | low;
+            let c = cost[p] + cost[s ^ p];
+            if c < best {
+                best = c;
+                best_split[s] = p;
+            }
+            if q == 0 {
+                break;
+            }
+            q = (q - 1) & rest;
+        }
+        cost[s] = TRAVERSAL_COST * bbox[s].surface_area() + best;
+    }
+
+    let all = subsets - 1;
+    if cost[all] >= root.cost * (1.0 - 1e-9) {
+        return;
+    }
+
+    // 按最优划分把内部节点依次分配给各子集
+    let mut free = interiors.into_iter();
+    let mut stack = vec![(free.next().unwrap(), all)];
+    while let Some((index, s)) = stack.pop() {
+        let mut children = [0u32; 2];
+        for (c, part) in [best_split[s], s ^ best_split[s]].into_iter().enumerate() {
+            children[c] = if part.count_ones() == 1 {
+                leaves[part.trailing_zeros() as usize]
+            } else {
+                let child = free.next().unwrap();
+                stack.push((child, part));
+                child
+            };
+        }
+        nodes[local(index)].children = children;
+        nodes[local(index)].count = 0; // 标记为待更新
+    }
+
+    update_subtree(nodes, base, base as u32);
+}
+
+/// 递归更新 treelet 内部节点的包围盒、代价与图元数，遇到图元数已知的节点即停止
+fn update_subtree(nodes: &mut [BuildNode], base: usize, index: u32) {
+    let i = index as usize - base;
+    if nodes[i].count != 0 {
+        return;
+    }
+
+    let [left, right] = nodes[i].children;
+    update_subtree(nodes, base, left);
+    update_subtree(nodes, base, right);
+
+    let (l, r) = (nodes[left as usize - base], nodes[right as usize - base]);
+    let bbox = Aabb::from_aabbs(l.bbox, r.bbox);
+    nodes[i].bbox = bbox;
+    nodes[i].cost = TRAVERSAL_COST * bbox.surface_area() + l.cost + r.cost;
+    nodes[i].count = l.count + r.count;
+}
+
+/// 把节点重新按先序排列
+fn reorder(nodes: &[BuildNode]) -> Vec<BuildNode> {
+    let mut output = Vec::with_capacity(nodes.len());
+    let mut stack = vec![(0u32, usize::MAX, 0)]; // (原下标, 父节点新下标, 第几个子节点)
+    while let Some((index, parent, slot)) = stack.pop() {
+        let new_index = output.len();
+        if parent != usize::MAX {
+            let parent: &mut BuildNode = &mut output[parent];
+            parent.children[slot] = new_index as u32;
+        }
+
+        let node = nodes[index as usize];
+        output.push(node);
+        if !node.is_leaf() {
+            stack.push((node.children[1], new_index, 1));
+            stack.push((node.children[0], new_index, 0));
+        }
+    }
+    output
+}
+
+/// 把构建树转换为 `FlatNode`，按 SAH 代价合并小子树
+struct Output<'a> {
+    nodes: Vec<FlatNode>,
+    order: Vec<usize>,
+    tree: &'a [BuildNode],
+    sorted: &'a [usize],
+    max_leaf_size: usize,
+}
+
+impl Output<'_> {
+    fn emit(&mut self, index: usize) -> BvhStats {
+        let node = self.tree[index];
+        let count = node.count as usize;
+        let leaf_cost = INTERSECT_COST * node.bbox.surface_area() * count as f64;
+
+        if node.is_leaf() || (count <= self.max_leaf_size && leaf_cost <= node.cost) {
+            self.nodes.push(FlatNode {
+                bbox: node.bbox,
+                offset: self.order.len() as u32,
+                count: node.count,
+                axis: 0,
+            });
+            self.collect_primitives(index);
+            return BvhStats::leaf(count);
+        }
+
+        // 近侧子节点优先遍历：取两个子节点中心相距最远的轴
+        let [left, right] = node.children.map(|c| c as usize);
+        let left_box = self.tree[left].bbox;
+        let right_box = self.tree[right].bbox;
+        let delta = right_box.centroid() - left_box.centroid();
+        let axis = (0..3)
+            .max_by(|&a, &b| delta[a].abs().total_cmp(&delta[b].abs()))
+            .unwrap();
+
+        let node_index = self.nodes.len();
+        self.nodes.push(FlatNode {
+            bbox: node.bbox,
+            offset: 0,
+            count: 0,
+            axis: axis as u8,
+        });
+
+        // 划分轴上右子节点应在较大一侧，否则交换
+        let (left, right) = if delta[axis] < 0.0 {
+            (right, left)
+        } else {
+            (left, right)
+        };
+        let left_stats = self.emit(left);
+        let right_index = self.nodes.len();
+        let right_stats = self.emit(right);
+        self.nodes[node_index].offset = right_index as u32;
+
+        BvhStats::interior(
+            &node.bbox,
+            (&self.tree[left].bbox, &left_stats),
+            (&self.tree[right].bbox, &right_stats),
+        )
+    }
+
+    fn collect_primitives(&mut self, index: usize) {
+        let node = self.tree[index];
+        if node.is_leaf() {
+            self.order.push(self.sorted[node.start as usize]);
+        } else {
+            self.collect_primitives(node.children[0] as usize);
+            self.collect_primitives(node.children[1] as usize);
+        }
+    }
+}
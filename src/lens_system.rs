@@ -0,0 +1,345 @@
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3, dot, unit_vector};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// 镜头中的一个球面（或光阑）
+///
+/// 单位均为毫米，顺序从物方（场景一侧）到像方（胶片一侧）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensElement {
+    pub curvature_radius: f64, // 曲率半径，0 表示光阑（平面）
+    pub thickness: f64,        // 沿光轴到下一个面的距离
+    pub ior: f64,              // 该面之后（靠胶片一侧）介质的折射率，0 表示光阑
+    pub aperture_radius: f64,  // 通光半径
+}
+
+/// 由多片球面镜组成的真实镜头
+///
+/// 采用 pbrt 的相机空间约定：胶片位于 z = 0，镜头沿 +z 方向排列，场景在更远的 +z 处
+#[derive(Debug, Clone, PartialEq)]
+pub struct LensSystem {
+    pub elements: Vec<LensElement>,
+    pub film_diagonal: f64,   // 胶片对角线长度（毫米）
+    pub units_per_meter: f64, // 每米对应的场景单位数
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>) -> Self {
+        Self {
+            elements,
+            film_diagonal: 35.0,
+            units_per_meter: 1.0,
+        }
+    }
+
+    /// 从文本表格加载镜头数据
+    ///
+    /// 每行四列：曲率半径、厚度、折射率、通光口径（直径），`#` 开头为注释。
+    /// 最后一行的厚度是后镜片到胶片的初始距离，对焦时会被调整
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path.as_ref())?;
+        Self::parse(&text)
+    }
+
+    /// 解析镜头数据表
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut elements = Vec::new();
+
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+            if values.len() != 4 {
+                return Err(format!(
+                    "line {}: expected 4 columns, found {}",
+                    line_no + 1,
+                    values.len()
+                )
+                .into());
+            }
+
+            elements.push(LensElement {
+                curvature_radius: values[0],
+                thickness: values[1],
+                ior: values[2],
+                aperture_radius: values[3] / 2.0,
+            });
+        }
+
+        if elements.is_empty() {
+            return Err("lens file contains no elements".into());
+        }
+
+        Ok(Self::new(elements))
+    }
+
+    /// 后镜片到胶片的距离
+    pub fn rear_z(&self) -> f64 {
+        self.elements.last().map_or(0.0, |e| e.thickness)
+    }
+
+    /// 前镜片到胶片的距离
+    pub fn front_z(&self) -> f64 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    /// 后镜片的通光半径
+    pub fn rear_aperture_radius(&self) -> f64 {
+        self.elements.last().map_or(0.0, |e| e.aperture_radius)
+    }
+
+    /// 从胶片一侧追踪射线穿过镜头，被遮挡或全反射时返回 `None`
+    pub fn trace_from_film(&self, r_camera: &Ray) -> Option<Ray> {
+        let mut element_z = 0.0;
+        // 转到镜头空间（z 取反）
+        let mut origin = flip_z(*r_camera.origin());
+        let mut direction = flip_z(*r_camera.direction());
+
+        for i in (0..self.elements.len()).rev() {
+            let element = &self.elements[i];
+            element_z -= element.thickness;
+
+            let is_stop = element.curvature_radius == 0.0;
+            let (t, normal) = if is_stop {
+                if direction.z() >= 0.0 {
+                    return None;
+                }
+                ((element_z - origin.z()) / direction.z(), Vec3::default())
+            } else {
+                let radius = element.curvature_radius;
+                let z_center = element_z + radius;
+                intersect_spherical_element(radius, z_center, origin, direction)?
+            };
+
+            let p_hit = origin + t * direction;
+            if p_hit.x() * p_hit.x() + p_hit.y() * p_hit.y()
+                > element.aperture_radius * element.aperture_radius
+            {
+                return None;
+            }
+            origin = p_hit;
+
+            if !is_stop {
+                let eta_i = element.ior;
+                let eta_t = if i > 0 && self.elements[i - 1].ior != 0.0 {
+                    self.elements[i - 1].ior
+                } else {
+                    1.0
+                };
+                direction = refract(unit_vector(-direction), normal, eta_i / eta_t)?;
+            }
+        }
+
+        Some(Ray::with_origin_dir_time(
+            flip_z(origin),
+            flip_z(direction),
+            r_camera.time(),
+        ))
+    }
+
+    /// 从场景一侧追踪射线穿过镜头（用于计算厚透镜近似）
+    pub fn trace_from_scene(&self, r_camera: &Ray) -> Option<Ray> {
+        let mut element_z = -self.front_z();
+        let mut origin = flip_z(*r_camera.origin());
+        let mut direction = flip_z(*r_camera.direction());
+
+        for i in 0..self.elements.len() {
+            let element = &self.elements[i];
+
+            let is_stop = element.curvature_radius == 0.0;
+            let (t, normal) = if is_stop {
+                if direction.z() <= 0.0 {
+                    return None;
+                }
+                ((element_z - origin.z()) / direction.z(), Vec3::default())
+            } else {
+                let radius = element.curvature_radius;
+                let z_center = element_z + radius;
+                intersect_spherical_element(radius, z_center, origin, direction)?
+            };
+
+            let p_hit = origin + t * direction;
+            if p_hit.x() * p_hit.x() + p_hit.y() * p_hit.y()
+                > element.aperture_radius * element.aperture_radius
+            {
+                return None;
+            }
+            origin = p_hit;
+
+            if !is_stop {
+                let eta_i = if i == 0 || self.elements[i - 1].ior == 0.0 {
+                    1.0
+                } else {
+                    self.elements[i - 1].ior
+                };
+                let eta_t = if element.ior != 0.0 { element.ior } else { 1.0 };
+                direction = refract(unit_vector(-direction), normal, eta_i / eta_t)?;
+            }
+
+            element_z += element.thickness;
+        }
+
+        Some(Ray::with_origin_dir_time(
+            flip_z(origin),
+            flip_z(direction),
+            r_camera.time(),
+        ))
+    }
+
+    /// 厚透镜近似：返回物方、像方的主平面位置和焦点位置 (pz, fz)
+    fn thick_lens_approximation(&self) -> Option<([f64; 2], [f64; 2])> {
+        let x = 0.001 * self.film_diagonal;
+
+        // 平行于光轴、从场景射入的射线
+        let r_scene = Ray::with_origin_dir(
+            Point3::new(x, 0.0, self.front_z() + 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        let r_film = self.trace_from_scene(&r_scene)?;
+        let (pz0, fz0) = compute_cardinal_points(&r_scene, &r_film);
+
+        // 平行于光轴、从胶片射出的射线
+        let r_film = Ray::with_origin_dir(
+            Point3::new(x, 0.0, self.rear_z() - 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let r_scene = self.trace_from_film(&r_film)?;
+        let (pz1, fz1) = compute_cardinal_points(&r_film, &r_scene);
+
+        Some(([pz0, pz1], [fz0, fz1]))
+    }
+
+    /// 等效焦距（毫米）
+    pub fn focal_length(&self) -> Option<f64> {
+        let (pz, fz) = self.thick_lens_approximation()?;
+        Some(fz[0] - pz[0])
+    }
+
+    /// 调整后镜片到胶片的距离，使距胶片 `focus_distance`（场景单位）处的物体清晰
+    pub fn focus(&mut self, focus_distance: f64) -> Result<(), String> {
+        let (pz, fz) = self
+            .thick_lens_approximation()
+            .ok_or("paraxial ray did not pass through the lens")?;
+
+        let f = fz[0] - pz[0];
+        let z = -focus_distance / self.units_per_meter * 1000.0;
+        let c = (pz[1] - z - pz[0]) * (pz[1] - z - 4.0 * f - pz[0]);
+        if c <= 0.0 {
+            return Err(format!(
+                "focus distance {} is too short for this lens",
+                focus_distance
+            ));
+        }
+
+        let delta = 0.5 * (pz[1] - z + pz[0] - c.sqrt());
+        if let Some(rear) = self.elements.last_mut() {
+            rear.thickness += delta;
+        }
+        Ok(())
+    }
+
+    /// 胶片中心的通光比例：在后镜片上规则取点，统计能穿过镜头的射线比例
+    pub fn center_transmittance(&self) -> f64 {
+        const N: i32 = 64;
+        let rear_radius = self.rear_aperture_radius();
+        let mut total = 0;
+        let mut passed = 0;
+
+        for a in 0..N {
+            for b in 0..N {
+                let x = ((a as f64 + 0.5) / N as f64 * 2.0 - 1.0) * rear_radius;
+                let y = ((b as f64 + 0.5) / N as f64 * 2.0 - 1.0) * rear_radius;
+                if x * x + y * y > rear_radius * rear_radius {
+                    continue;
+                }
+                total += 1;
+
+                let r = Ray::with_origin_dir(Point3::default(), Vec3::new(x, y, self.rear_z()));
+                if self.trace_from_film(&r).is_some() {
+                    passed += 1;
+                }
+            }
+        }
+
+        if total == 0 {
+            0.0
+        } else {
+            passed as f64 / total as f64
+        }
+    }
+}
+
+fn flip_z(v: Vec3) -> Vec3 {
+    Vec3::new(v.x(), v.y(), -v.z())
+}
+
+/// 射线与球心在光轴 z_center 处、半径为 radius 的球面求交，返回 (t, 朝向入射方的法向)
+fn intersect_spherical_element(
+    radius: f64,
+    z_center: f64,
+    origin: Point3,
+    direction: Vec3,
+) -> Option<(f64, Vec3)> {
+    let o = origin - Vec3::new(0.0, 0.0, z_center);
+    let a = direction.length_squared();
+    let b = 2.0 * dot(&direction, &o);
+    let c = o.length_squared() - radius * radius;
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let q = if b < 0.0 {
+        -0.5 * (b - sqrtd)
+    } else {
+        -0.5 * (b + sqrtd)
+    };
+    let (mut t0, mut t1) = (q / a, c / q);
+    if t0 > t1 {
+        std::mem::swap(&mut t0, &mut t1);
+    }
+
+    // 根据射线方向与球面凹凸选择正确的交点
+    let use_closer = (direction.z() > 0.0) ^ (radius < 0.0);
+    let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+    if t < 0.0 {
+        return None;
+    }
+
+    let mut normal = unit_vector(o + t * direction);
+    if dot(&normal, &-direction) < 0.0 {
+        normal = -normal;
+    }
+    Some((t, normal))
+}
+
+/// 折射；`wi` 指向入射方，`eta` 为入射侧与出射侧折射率之比，全反射时返回 `None`
+fn refract(wi: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = dot(&n, &wi);
+    let sin2_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    let sin2_theta_t = eta * eta * sin2_theta_i;
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(eta * -wi + (eta * cos_theta_i - cos_theta_t) * n)
+}
+
+/// 由入射和出射射线求主平面与焦点在光轴上的位置
+fn compute_cardinal_points(r_in: &Ray, r_out: &Ray) -> (f64, f64) {
+    let tf = -r_out.origin().x() / r_out.direction().x();
+    let fz = -r_out.at(tf).z();
+    let tp = (r_in.origin().x() - r_out.origin().x()) / r_out.direction().x();
+    let pz = -r_out.at(tp).z();
+    (pz, fz)
+}
@@ -1,42 +1,71 @@
 pub mod aabb;
+pub mod animation;
+pub mod aperture;
 pub mod bvh;
 pub mod camera;
+pub mod checkpoint;
 pub mod color;
 pub mod constant_medium;
+pub mod debug_view;
+pub mod exposure;
+pub mod film;
+pub mod flat_bvh;
 pub mod hittable;
 pub mod hittable_list;
+pub mod instance;
 pub mod interval;
+pub mod lbvh;
+pub mod lens_system;
 pub mod material;
 pub mod mesh;
+pub mod mesh_cache;
+pub mod mtl;
 pub mod obj_loader;
 pub mod onb;
+pub mod packet;
 pub mod pdf;
 pub mod perlin;
+pub mod projection;
 pub mod quad;
 pub mod ray;
 pub mod rtw_stb_image;
 pub mod rtweekend;
+pub mod shutter;
 pub mod sphere;
+pub mod stats;
+pub mod stereo;
 pub mod texture;
 pub mod triangle;
+pub mod triangle_mesh;
 pub mod vec3;
+pub mod wide_bvh;
 
-use crate::bvh::BvhNode;
-use crate::camera::Camera;
+use crate::aabb::Aabb;
+use crate::bvh::{BvhLayout, BvhNode, BvhOptions, build_bvh};
+use crate::camera::{Camera, FocusTarget};
 use crate::color::Color;
-use crate::hittable::{Hittable, RotateY, Translate};
+use crate::debug_view::DebugView;
+use crate::flat_bvh::FlatBvh;
+use crate::hittable::{HitRecord, Hittable, RotateY, Translate};
 use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::interval::Interval;
 use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
-use crate::mesh::Mesh;
+use crate::mesh_cache::MeshCache;
+use crate::obj_loader::{NormalMode, ObjData, ObjOptions, load_obj};
 use crate::quad::{Quad, box_new};
-use crate::rtweekend::{random_double, random_double_range};
+use crate::ray::Ray;
+use crate::rtweekend::{random_double, random_double_range, random_int, seed_thread_rng};
 use crate::sphere::Sphere;
 use crate::texture::{CheckerTexture, NoiseTexture, SolidColor};
 use crate::triangle::Triangle;
-use crate::vec3::{Point3, Vec3};
-use obj_loader::ObjModel;
+use crate::triangle_mesh::UvProjection;
+use crate::vec3::{Point3, Vec3, random_unit_vector};
+use glam::{DAffine3, DQuat, DVec3};
+use std::fs::File;
+use std::io::BufWriter;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 fn for_output13() {
     let mut world = HittableList::new();
@@ -91,7 +120,8 @@ fn for_output13() {
     cam.vup = Vec3::new(0.0, 1.0, 0.0);
 
     cam.defocus_angle = 10.0;
-    cam.focus_dist = 3.4;
+    // 对焦到中间的球上
+    cam.focus_target = Some(FocusTarget::Point(Point3::new(0.0, 0.0, -1.2)));
 
     // cam.render(&world);
 }
@@ -649,6 +679,9 @@ fn cornell_smoke() {
 }
 
 fn final_scene(image_width: usize, samples_per_pixel: usize, max_depth: usize) {
+    // 固定场景随机数，断点续渲时才能识别为同一场景
+    seed_thread_rng(2025);
+
     let mut boxes1 = HittableList::new();
     let ground = Arc::new(Lambertian::new(Color::new(0.48, 0.83, 0.53)));
 
@@ -784,21 +817,27 @@ fn final_scene(image_width: usize, samples_per_pixel: usize, max_depth: usize) {
 
     cam.defocus_angle = 0.0;
 
+    // 高样本数渲染耗时很长，定期保存断点，中断后再次运行会自动续渲
+    cam.checkpoint_path = Some("final_scene.ckpt".into());
+    cam.checkpoint_interval = 5;
+
     // cam.render(&world);
 }
 
 fn cornell_box_with_obj() {
     let mut world = HittableList::new();
 
-    // 加载 OBJ 模型
+    // 加载 OBJ 模型，解析结果与 BVH 缓存在 cache 目录中，再次运行时直接读取
     let material = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.9), 0.2));
-    let bunny = ObjModel::load(
-        "models/cottage_obj.obj", // 替换为你的OBJ文件路径
-        material,
-        1000.0,                           // 缩放
-        Point3::new(278.0, 100.0, 280.0), // 位置
-    )
-    .expect("Failed to load OBJ model");
+    let options = ObjOptions {
+        scale: 1000.0,                                                          // 缩放
+        transform: DAffine3::from_translation(DVec3::new(278.0, 100.0, 280.0)), // 位置
+        material: Some(material),
+        ..Default::default()
+    };
+    let bunny = MeshCache::new("cache")
+        .load_obj("models/cottage_obj.obj", &options) // 替换为你的OBJ文件路径
+        .expect("Failed to load OBJ model");
 
     world.add(Arc::new(bunny));
 
@@ -838,8 +877,13 @@ fn test_mesh_rendering() {
 
     // 加载测试模型
     println!("Loading OBJ model...");
-    let mesh =
-        Mesh::from_obj("models/test_triangle.obj", material).expect("Failed to load OBJ file");
+    let options = ObjOptions {
+        material: Some(material),
+        ..Default::default()
+    };
+    let mesh = MeshCache::new("cache")
+        .load_obj("models/test_triangle.obj", &options)
+        .expect("Failed to load OBJ file");
     world.add(Arc::new(mesh));
 
     let mut cam = Camera::new();
@@ -894,7 +938,557 @@ fn test_triangle() {
     // cam.render(&world);
 }
 
+/// 实例化示例：一棵树只建一次底层 BVH，10000 个实例各自带变换和材质
+fn instanced_forest() {
+    seed_thread_rng(2025);
+
+    // 底层几何：树干加树冠，所有实例共享
+    let bark = Arc::new(Lambertian::new(Color::new(0.35, 0.22, 0.1)));
+    let mut tree_parts = HittableList::new();
+    tree_parts.add(box_new(
+        Point3::new(-0.1, 0.0, -0.1),
+        Point3::new(0.1, 1.0, 0.1),
+        bark.clone(),
+    ));
+    tree_parts.add(Arc::new(Sphere::new(Point3::new(0.0, 1.5, 0.0), 0.7, bark)));
+    let tree = BvhNode::new(&tree_parts);
+
+    let foliage: Vec<Arc<dyn Material + Send + Sync>> = vec![
+        Arc::new(Lambertian::new(Color::new(0.1, 0.4, 0.1))),
+        Arc::new(Lambertian::new(Color::new(0.2, 0.5, 0.15))),
+        Arc::new(Lambertian::new(Color::new(0.7, 0.4, 0.05))),
+    ];
+
+    let mut instances = HittableList::new();
+    for i in 0..100 {
+        for j in 0..100 {
+            let x = (i as f64 - 50.0) * 2.0 + random_double_range(-0.6, 0.6);
+            let z = (j as f64) * 2.0 + random_double_range(-0.6, 0.6);
+            let scale = random_double_range(0.7, 1.3);
+            let angle = random_double_range(0.0, 2.0 * std::f64::consts::PI);
+            let transform = DAffine3::from_scale_rotation_translation(
+                DVec3::splat(scale),
+                DQuat::from_rotation_y(angle),
+                DVec3::new(x, 0.0, z),
+            );
+            let material = foliage[random_int(0, foliage.len() as i32 - 1) as usize].clone();
+            instances.add(Arc::new(
+                Instance::new(tree.clone(), transform).with_material(material),
+            ));
+        }
+    }
+
+    let mut world = HittableList::new();
+    world.add(build_bvh(
+        &instances,
+        BvhOptions::sah(16, 1),
+        BvhLayout::Wide,
+    ));
+    world.add(Arc::new(Quad::new(
+        Point3::new(-200.0, 0.0, -50.0),
+        Vec3::new(400.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 400.0),
+        Arc::new(Lambertian::new(Color::new(0.4, 0.35, 0.25))),
+    )));
+
+    let sun = Arc::new(Sphere::new(
+        Point3::new(-300.0, 400.0, -200.0),
+        60.0,
+        Arc::new(DiffuseLight::from_color(Color::new(15.0, 14.0, 12.0))),
+    ));
+    world.add(sun.clone());
+    let mut lights = HittableList::new();
+    lights.add(sun);
+
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 600;
+    cam.samples_per_pixel = 64;
+    cam.max_depth = 10;
+    cam.background = Color::new(0.6, 0.75, 0.95);
+
+    cam.vfov = 40.0;
+    cam.lookfrom = Point3::new(0.0, 8.0, -20.0);
+    cam.lookat = Point3::new(0.0, 0.0, 30.0);
+
+    // cam.render(Arc::new(world), Arc::new(lights));
+}
+
+/// 比较不同 BVH 构建方式：构建时间、树的统计信息和遍历随机射线的时间
+///
+/// 场景仿照 final_scene：400 个地面盒子加上聚成一团的 1000 个小球，全部放进同一棵树
+fn bvh_benchmark() {
+    seed_thread_rng(2025);
+
+    let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mut objects = HittableList::new();
+
+    for i in 0..20 {
+        for j in 0..20 {
+            let w = 100.0;
+            let x0 = -1000.0 + i as f64 * w;
+            let z0 = -1000.0 + j as f64 * w;
+            let y1 = random_double_range(1.0, 101.0);
+            objects.add(box_new(
+                Point3::new(x0, 0.0, z0),
+                Point3::new(x0 + w, y1, z0 + w),
+                material.clone(),
+            ));
+        }
+    }
+    for _ in 0..1000 {
+        objects.add(Arc::new(Sphere::new(
+            Point3::random_range(0.0, 165.0) + Vec3::new(-100.0, 270.0, 395.0),
+            10.0,
+            material.clone(),
+        )));
+    }
+
+    // 从相机位置射向场景中的随机点
+    let origin = Point3::new(478.0, 278.0, -600.0);
+    let rays: Vec<Ray> = (0..500_000)
+        .map(|_| {
+            let target = Point3::new(
+                random_double_range(-1000.0, 1000.0),
+                random_double_range(0.0, 600.0),
+                random_double_range(-1000.0, 1000.0),
+            );
+            Ray::with_origin_dir(origin, target - origin)
+        })
+        .collect();
+
+    for (name, options) in [
+        ("median", BvhOptions::median()),
+        ("sah, leaf 1", BvhOptions::sah(16, 1)),
+        ("sah, leaf 4", BvhOptions::sah(16, 4)),
+        ("lbvh", BvhOptions::lbvh(0, 1)),
+        ("lbvh + treelets", BvhOptions::lbvh(2, 1)),
+    ] {
+        println!(
+            "{}: {}",
+            name,
+            BvhNode::with_options(&objects, options).stats()
+        );
+        for layout in [BvhLayout::Tree, BvhLayout::Flat, BvhLayout::Wide] {
+            let start = Instant::now();
+            let bvh = build_bvh(&objects, options, layout);
+            let build_time = start.elapsed();
+            bvh_benchmark_trace(&format!("{:?}", layout), bvh.as_ref(), build_time, &rays);
+        }
+    }
+}
+
+/// 经纬网格划分的球面，半径按 Perlin 湍流起伏，共 `2 * segments * rings` 个三角形
+///
+/// 三角形大小不一、两极处细长，比均匀网格更接近扫描得到的模型，用于不依赖外部文件的网格测试
+fn bumpy_sphere(segments: usize, rings: usize) -> (Vec<Point3>, Vec<[u32; 3]>) {
+    let noise = perlin::Perlin::new();
+    let mut positions = Vec::with_capacity((segments + 1) * (rings + 1));
+    for j in 0..=rings {
+        let theta = std::f64::consts::PI * j as f64 / rings as f64;
+        for i in 0..=segments {
+            let phi = 2.0 * std::f64::consts::PI * i as f64 / segments as f64;
+            let dir = Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            positions.push(dir * (1.0 + 0.4 * noise.turb(&(dir * 3.0), 7)));
+        }
+    }
+
+    let vertex = |i: usize, j: usize| (j * (segments + 1) + i) as u32;
+    let mut indices = Vec::with_capacity(2 * segments * rings);
+    for j in 0..rings {
+        for i in 0..segments {
+            indices.push([vertex(i, j), vertex(i, j + 1), vertex(i + 1, j + 1)]);
+            indices.push([vertex(i, j), vertex(i + 1, j + 1), vertex(i + 1, j)]);
+        }
+    }
+    (positions, indices)
+}
+
+/// 比较大型网格在不同构建方式和 BVH 布局下的构建时间、树的质量与遍历速度
+///
+/// 网格由 `bumpy_sphere` 生成，约 50 万个三角形
+fn mesh_bvh_benchmark() {
+    seed_thread_rng(2025);
+
+    let material: Arc<dyn Material + Send + Sync> =
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let (positions, indices) = bumpy_sphere(512, 512);
+    let mut triangles = HittableList::new();
+    for [a, b, c] in indices {
+        triangles.add(Arc::new(Triangle::new(
+            positions[a as usize],
+            positions[b as usize],
+            positions[c as usize],
+            material.clone(),
+        )));
+    }
+
+    let rays = rays_into_box(&triangles.bounding_box(), 500_000);
+
+    for (name, options) in [
+        ("sah", BvhOptions::sah(16, 1)),
+        ("lbvh", BvhOptions::lbvh(0, 1)),
+        ("lbvh + treelets", BvhOptions::lbvh(2, 1)),
+    ] {
+        println!("{}: {}", name, FlatBvh::new(&triangles, options).stats());
+        for layout in [BvhLayout::Tree, BvhLayout::Flat, BvhLayout::Wide] {
+            let start = Instant::now();
+            let bvh = build_bvh(&triangles, options, layout);
+            let build_time = start.elapsed();
+            bvh_benchmark_trace(&format!("{:?}", layout), bvh.as_ref(), build_time, &rays);
+        }
+    }
+}
+
+/// 带图片纹理的网格：使用 OBJ 中的纹理坐标，文件中没有时按球面投影生成
+fn textured_mesh() {
+    let earth_texture = Arc::new(texture::ImageTexture::new("earthmap.jpg"));
+    let earth_surface = Arc::new(Lambertian::from_texture(earth_texture));
+    let options = ObjOptions {
+        material: Some(earth_surface),
+        ..Default::default()
+    };
+    let mut globe = load_obj("models/sphere.obj", &options).expect("Failed to load OBJ model");
+    if globe.uvs().is_empty() {
+        globe.generate_uvs(UvProjection::Spherical);
+    }
+
+    let mut world = HittableList::new();
+    world.add(Arc::new(globe));
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+    cam.background = Color::new(0.70, 0.80, 1.00);
+
+    cam.vfov = 20.0;
+    cam.lookfrom = Point3::new(0.0, 0.0, 12.0);
+    cam.lookat = Point3::new(0.0, 0.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    // cam.render(&world);
+}
+
+/// 使用 MTL 文件中材质的模型：漫反射、金属、玻璃与自发光材质按面指定
+fn obj_with_materials() {
+    let mut world = HittableList::new();
+
+    // 不指定材质，按 MTL 文件逐面创建
+    let cottage = load_obj("models/cottage_obj.obj", &ObjOptions::default())
+        .expect("Failed to load OBJ model");
+    world.add(Arc::new(cottage));
+
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 20;
+    cam.background = Color::new(0.70, 0.80, 1.00);
+
+    cam.vfov = 40.0;
+    cam.lookfrom = Point3::new(0.0, 0.3, -1.0);
+    cam.lookat = Point3::new(0.0, 0.1, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.0;
+
+    // cam.render(&world);
+}
+
+/// 比较共享顶点的 `TriangleMesh` 与逐面 `Triangle` 加默认参数的 BVH 的内存占用和求交速度
+fn mesh_memory_benchmark(path: &str) {
+    seed_thread_rng(2025);
+
+    let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let options = ObjOptions {
+        normals: NormalMode::Flat,
+        material: Some(material),
+        ..Default::default()
+    };
+    let model = ObjData::load(path, &options).expect("Failed to load OBJ model");
+    let mut triangles = HittableList::new();
+    for triangle in model.triangles() {
+        triangles.add(triangle);
+    }
+
+    let start = Instant::now();
+    let flat = FlatBvh::new(&triangles, BvhOptions::sah(16, 1));
+    let flat_build = start.elapsed();
+    // 每个三角形一次 Arc 分配（两个引用计数加三角形本身）与列表中的一个胖指针
+    let per_triangle = size_of::<Triangle>()
+        + 2 * size_of::<usize>()
+        + size_of::<Arc<dyn Hittable + Send + Sync>>();
+    let flat_bytes = triangles.objects.len() * per_triangle + size_of_val(flat.nodes());
+
+    let start = Instant::now();
+    let mesh = model.into_mesh(options.bvh);
+    let mesh_build = start.elapsed();
+    let mesh_bytes = mesh.memory_bytes();
+
+    println!(
+        "triangles: {:.1} MB, mesh: {:.1} MB ({:.1}x smaller)",
+        flat_bytes as f64 / 1e6,
+        mesh_bytes as f64 / 1e6,
+        flat_bytes as f64 / mesh_bytes as f64
+    );
+
+    let rays = rays_into_box(&mesh.bounding_box(), 500_000);
+    bvh_benchmark_trace("tris", &flat, flat_build, &rays);
+    bvh_benchmark_trace("mesh", &mesh, mesh_build, &rays);
+}
+
+/// 比较逐帧重拟合与逐帧重建 BVH 的耗时，并观察树随物体运动的退化程度
+///
+/// 2000 个小球在 4 秒内从一团散开，按 24 帧每秒、180° 快门逐帧更新
+fn bvh_refit_benchmark() {
+    seed_thread_rng(2025);
+
+    let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mut objects = HittableList::new();
+    for _ in 0..2000 {
+        let center = Point3::random_range(-10.0, 10.0);
+        objects.add(Arc::new(Sphere::new_moving_timed(
+            center,
+            0.0,
+            center + 50.0 * random_unit_vector(),
+            4.0,
+            0.5,
+            material.clone(),
+        )));
+    }
+
+    let frame_rate = 24.0;
+    let shutter = |frame: i32| {
+        let time = frame as f64 / frame_rate;
+        Interval::new(time, time + 0.5 / frame_rate)
+    };
+
+    let mut bvh = FlatBvh::new(&objects, BvhOptions::sah(16, 1));
+    let mut rebuilt = FlatBvh::new(&objects, BvhOptions::sah(16, 1));
+    let mut refit_time = Duration::ZERO;
+    let mut rebuild_time = Duration::ZERO;
+    let mut rebuilds = 0;
+
+    for frame in 0..96 {
+        let start = Instant::now();
+        if bvh.refit(shutter(frame)) {
+            rebuilds += 1;
+        }
+        refit_time += start.elapsed();
+
+        let start = Instant::now();
+        rebuilt.rebuild(shutter(frame));
+        rebuild_time += start.elapsed();
+
+        if frame % 12 == 0 {
+            println!(
+                "frame {:>2}: refit SAH cost {:.2} (degradation {:.2}), rebuild SAH cost {:.2}",
+                frame,
+                bvh.stats().sah_cost,
+                bvh.degradation(),
+                rebuilt.stats().sah_cost
+            );
+        }
+    }
+
+    println!(
+        "refit: {:.2} ms ({} rebuilds), rebuild every frame: {:.2} ms",
+        refit_time.as_secs_f64() * 1000.0,
+        rebuilds,
+        rebuild_time.as_secs_f64() * 1000.0
+    );
+}
+
+/// 比较逐条追踪与成束追踪主射线的渲染时间
+///
+/// 场景为 300×300 网格的起伏地形（18 万个三角形）放在线性化 BVH 中，主射线高度相干
+fn packet_benchmark() {
+    seed_thread_rng(2025);
+
+    let ground = Arc::new(Lambertian::new(Color::new(0.45, 0.5, 0.35)));
+    let n = 300;
+    let height = |i: usize, j: usize| {
+        let (x, z) = (i as f64 * 0.1, j as f64 * 0.1);
+        0.6 * (x * 0.7).sin() * (z * 0.5).cos() + 0.2 * (x * 2.3 + z * 1.7).sin()
+    };
+    let point = |i: usize, j: usize| {
+        Point3::new(i as f64 * 0.2 - 30.0, height(i, j), j as f64 * 0.2 - 30.0)
+    };
+
+    let mut triangles = HittableList::new();
+    for i in 0..n {
+        for j in 0..n {
+            let (p00, p10) = (point(i, j), point(i + 1, j));
+            let (p01, p11) = (point(i, j + 1), point(i + 1, j + 1));
+            triangles.add(Arc::new(Triangle::new(p00, p10, p11, ground.clone())));
+            triangles.add(Arc::new(Triangle::new(p00, p11, p01, ground.clone())));
+        }
+    }
+
+    let sun = Arc::new(Sphere::new(
+        Point3::new(-50.0, 80.0, -20.0),
+        15.0,
+        Arc::new(DiffuseLight::from_color(Color::new(10.0, 10.0, 9.0))),
+    ));
+    let mut world = HittableList::new();
+    world.add(build_bvh(
+        &triangles,
+        BvhOptions::sah(16, 1),
+        BvhLayout::Flat,
+    ));
+    world.add(sun.clone());
+    let world: Arc<dyn Hittable + Send + Sync> = Arc::new(world);
+    let lights: Arc<dyn Hittable + Send + Sync> = Arc::new(HittableList::with_object(sun));
+
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 16;
+    cam.max_depth = 4;
+    cam.background = Color::new(0.6, 0.7, 0.9);
+    cam.vfov = 50.0;
+    cam.lookfrom = Point3::new(0.0, 6.0, -32.0);
+    cam.lookat = Point3::new(0.0, 0.0, 0.0);
+
+    for packet_size in [0, 4, 8] {
+        cam.packet_size = packet_size;
+        let start = Instant::now();
+        if let Err(e) = cam.render_film(Arc::clone(&world), Arc::clone(&lights)) {
+            eprintln!("\nERROR: {}", e);
+            return;
+        }
+        println!(
+            "\npacket size {}: {:.2} ms",
+            packet_size,
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+    }
+}
+
+/// 从包围盒外的随机位置射向包围盒内随机点的射线
+fn rays_into_box(bbox: &Aabb, count: usize) -> Vec<Ray> {
+    let center = Point3::new(
+        0.5 * (bbox.x.min + bbox.x.max),
+        0.5 * (bbox.y.min + bbox.y.max),
+        0.5 * (bbox.z.min + bbox.z.max),
+    );
+    let radius = Vec3::new(bbox.x.size(), bbox.y.size(), bbox.z.size()).length();
+    let random_in_box = || {
+        Point3::new(
+            random_double_range(bbox.x.min, bbox.x.max),
+            random_double_range(bbox.y.min, bbox.y.max),
+            random_double_range(bbox.z.min, bbox.z.max),
+        )
+    };
+    (0..count)
+        .map(|_| {
+            let origin = center + radius * random_unit_vector();
+            Ray::with_origin_dir(origin, random_in_box() - origin)
+        })
+        .collect()
+}
+
+fn bvh_benchmark_trace(name: &str, bvh: &dyn Hittable, build_time: Duration, rays: &[Ray]) {
+    let start = Instant::now();
+    let mut hits = 0;
+    for r in rays {
+        let mut rec = HitRecord::default();
+        if bvh.hit(r, Interval::new(0.001, f64::INFINITY), &mut rec) {
+            hits += 1;
+        }
+    }
+    let trace_time = start.elapsed();
+
+    println!(
+        "    {:>5}: build {:.2} ms, {} rays in {:.2} ms ({} hits)",
+        name,
+        build_time.as_secs_f64() * 1000.0,
+        rays.len(),
+        trace_time.as_secs_f64() * 1000.0,
+        hits
+    );
+}
+
+/// 调试视图：同一场景分别输出 BVH 节点访问数、图元求交数、反弹次数和材质类型的伪彩色图
+fn debug_views() {
+    seed_thread_rng(2025);
+
+    let mut spheres = HittableList::new();
+    let materials: Vec<Arc<dyn Material + Send + Sync>> = vec![
+        Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.3))),
+        Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.1)),
+        Arc::new(Dielectric::new(1.5)),
+    ];
+    for i in 0..1000 {
+        let center = Point3::new(
+            random_double_range(-8.0, 8.0),
+            random_double_range(0.3, 4.0),
+            random_double_range(-4.0, 8.0),
+        );
+        let material = materials[i % materials.len()].clone();
+        spheres.add(Arc::new(Sphere::new(center, 0.3, material)));
+    }
+
+    let light = Arc::new(Quad::new(
+        Point3::new(-3.0, 8.0, -3.0),
+        Vec3::new(6.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 6.0),
+        Arc::new(DiffuseLight::from_color(Color::new(8.0, 8.0, 8.0))),
+    ));
+    let mut world = HittableList::new();
+    world.add(BvhNode::new(&spheres));
+    world.add(Arc::new(Quad::new(
+        Point3::new(-50.0, 0.0, -50.0),
+        Vec3::new(100.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 100.0),
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    )));
+    world.add(light.clone());
+    let world: Arc<dyn Hittable + Send + Sync> = Arc::new(world);
+    let lights: Arc<dyn Hittable + Send + Sync> = Arc::new(HittableList::with_object(light));
+
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 16;
+    cam.max_depth = 10;
+    cam.background = Color::new(0.1, 0.1, 0.15);
+    cam.vfov = 50.0;
+    cam.lookfrom = Point3::new(0.0, 5.0, -14.0);
+    cam.lookat = Point3::new(0.0, 1.5, 2.0);
+
+    let views = [
+        (DebugView::NodesVisited, "debug_nodes.ppm"),
+        (DebugView::PrimitiveTests, "debug_tests.ppm"),
+        (DebugView::PathDepth, "debug_depth.ppm"),
+        (DebugView::Material, "debug_material.ppm"),
+    ];
+    for (view, path) in views {
+        cam.debug_view = Some(view);
+        let film = match cam.render_film(Arc::clone(&world), Arc::clone(&lights)) {
+            Ok(film) => film,
+            Err(e) => {
+                eprintln!("\nERROR: {}", e);
+                return;
+            }
+        };
+        let file = File::create(path).expect("Failed to create output file");
+        film.write_ppm(&mut BufWriter::new(file))
+            .expect("Failed to write image");
+    }
+}
+
 fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
     let start = Instant::now(); // 开始计时
 
     // last_picture_the_first_book(); // bouncing spheres
@@ -911,6 +1505,15 @@ fn main() {
     // cornell_box_with_obj();
     // test_mesh_rendering();
     // test_triangle();
+    // instanced_forest();
+    // bvh_benchmark();
+    // mesh_bvh_benchmark();
+    // mesh_memory_benchmark("models/cottage_obj.obj");
+    // textured_mesh();
+    // obj_with_materials();
+    // bvh_refit_benchmark();
+    // packet_benchmark();
+    // debug_views();
 
     let elapsed = start.elapsed();
     println!("\n渲染完成,用时: {:.2}秒", elapsed.as_secs_f64());
@@ -34,6 +34,11 @@ impl fmt::Debug for ScatterRecord {
 }
 
 pub trait Material: Send + Sync + std::fmt::Debug {
+    /// 材质类型的名称，用于调试视图和统计输出；未实现的材质显示为 "unknown"
+    fn name(&self) -> &'static str {
+        "unknown"
+    }
+
     fn scatter(
         &self,
         _r_in: &Ray,
@@ -75,6 +80,10 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
+    fn name(&self) -> &'static str {
+        "lambertian"
+    }
+
     fn scatter(
         &self,
         _r_in: &Ray,
@@ -112,6 +121,10 @@ impl Material for Lambertian {
         // if cos_theta < 0.0 { 0.0 } else { cos_theta / PI }
         // 1.0 / (2.0 * PI)
 
+        // 着色法向与几何法向不同时，着色半球内的方向也可能穿入表面
+        if !rec.is_above(scattered.direction()) {
+            return 0.0;
+        }
         let cos_theta = dot(&rec.normal, &unit_vector(*scattered.direction()));
         if cos_theta < 0.0 { 0.0 } else { cos_theta / PI }
     }
@@ -133,6 +146,10 @@ impl Metal {
 }
 
 impl Material for Metal {
+    fn name(&self) -> &'static str {
+        "metal"
+    }
+
     fn scatter(
         &self,
         r_in: &Ray,
@@ -151,10 +168,14 @@ impl Material for Metal {
         srec.attenuation = self.albedo;
         srec.pdf_ptr = None;
         srec.skip_pdf = true;
-        srec.skip_pdf_ray = Some(Ray::with_origin_dir_time(rec.p, reflected, r_in.time()));
-
-        // dot(scattered.direction(), &rec.normal) > 0.0
-        true
+        srec.skip_pdf_ray = Some(Ray::with_origin_dir_time(
+            rec.spawn_origin(&reflected),
+            reflected,
+            r_in.time(),
+        ));
+
+        // 模糊反射或着色法向可能把方向推到表面以下，这部分光被吸收
+        rec.is_above(&reflected)
     }
 }
 
@@ -179,6 +200,10 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
+    fn name(&self) -> &'static str {
+        "dielectric"
+    }
+
     fn scatter(
         &self,
         r_in: &Ray,
@@ -212,7 +237,11 @@ impl Material for Dielectric {
         };
         // let refracted = refract(&unit_direction, &rec.normal, ri);
         // *scattered = Ray::with_origin_dir_time(rec.p, direction, r_in.time());
-        srec.skip_pdf_ray = Some(Ray::with_origin_dir_time(rec.p, direction, r_in.time()));
+        srec.skip_pdf_ray = Some(Ray::with_origin_dir_time(
+            rec.spawn_origin(&direction),
+            direction,
+            r_in.time(),
+        ));
         true
     }
 }
@@ -235,6 +264,10 @@ impl DiffuseLight {
 }
 
 impl Material for DiffuseLight {
+    fn name(&self) -> &'static str {
+        "diffuse_light"
+    }
+
     fn scatter(
         &self,
         _r_in: &Ray,
@@ -276,6 +309,10 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
+    fn name(&self) -> &'static str {
+        "isotropic"
+    }
+
     fn scatter(
         &self,
         _r_in: &Ray,
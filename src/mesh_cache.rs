@@ -0,0 +1,366 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::aabb::Aabb;
+use crate::bvh::{BvhStats, SplitMethod};
+use crate::checkpoint::{Fnv64, read_f64, read_u32, read_u64};
+use crate::flat_bvh::FlatNode;
+use crate::interval::Interval;
+use crate::material::MaterialPtr;
+use crate::obj_loader::{NormalMode, ObjData, ObjOptions, material_libraries};
+use crate::triangle_mesh::TriangleMesh;
+use crate::vec3::{Point3, Vec3};
+
+/// 缓存文件头部的魔数与版本号
+const MAGIC: &[u8; 4] = b"RTMC";
+const VERSION: u32 = 2;
+
+/// 网格及其 BVH 的磁盘缓存
+///
+/// 缓存文件以源文件（以及引用的 MTL 文件）内容和全部加载参数的哈希命名，其中任何一项改变都会换一个文件，
+/// 不会读到过期的数据。文件中保存 `TriangleMesh` 的顶点缓冲、按叶节点顺序排列的下标与逐面材质，
+/// 以及线性化的节点，命中时只需重新读取材质，不再解析 OBJ、也不再划分
+#[derive(Debug, Clone)]
+pub struct MeshCache {
+    pub dir: PathBuf,
+}
+
+impl MeshCache {
+    /// 缓存放在 `dir` 下
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 加载 OBJ 网格，结果与 `ObjData::load(path, options)?.into_mesh(options.bvh)` 相同
+    ///
+    /// 有可用的缓存时直接读取，否则完整加载并写入缓存；
+    /// 缓存损坏或写入失败只记录警告，不影响加载结果
+    pub fn load_obj(
+        &self,
+        path: impl AsRef<Path>,
+        options: &ObjOptions,
+    ) -> Result<TriangleMesh, Box<dyn Error>> {
+        let path = path.as_ref();
+        let start = Instant::now();
+        let key = key(path, options)?;
+        let cache_path = self.cache_path(path, key);
+
+        match CachedMesh::load(&cache_path, key) {
+            Ok(data) => {
+                let materials = ObjData::load_materials(path, options)?;
+                match data.into_mesh(materials) {
+                    Ok(mesh) => {
+                        log::info!(
+                            "Loaded {} triangles from {} in {:.2} ms",
+                            mesh.indices().len(),
+                            cache_path.display(),
+                            start.elapsed().as_secs_f64() * 1000.0
+                        );
+                        return Ok(mesh);
+                    }
+                    Err(e) => log::warn!("Ignoring mesh cache {}: {}", cache_path.display(), e),
+                }
+            }
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    log::warn!("Ignoring mesh cache {}: {}", cache_path.display(), e);
+                }
+            }
+        }
+
+        let mesh = ObjData::load(path, options)?.into_mesh(options.bvh);
+        if let Err(e) =
+            fs::create_dir_all(&self.dir).and_then(|_| CachedMesh::save(&mesh, &cache_path, key))
+        {
+            log::warn!("Failed to write mesh cache {}: {}", cache_path.display(), e);
+        }
+        Ok(mesh)
+    }
+
+    fn cache_path(&self, path: &Path, key: u64) -> PathBuf {
+        let stem = path
+            .file_stem()
+            .map_or("mesh".into(), |s| s.to_string_lossy());
+        self.dir.join(format!("{}-{:016x}.mesh", stem, key))
+    }
+}
+
+/// 源文件内容与影响加载结果的全部参数的哈希
+///
+/// 按 MTL 指定材质时，材质下标取决于 MTL 文件，它们的内容也参与哈希；
+/// 指定了统一材质时只记录这一点，材质本身在命中时由调用者提供
+fn key(path: &Path, options: &ObjOptions) -> io::Result<u64> {
+    let source = fs::read(path)?;
+    let mut hasher = Fnv64::new();
+    hasher.write_bytes(&source);
+
+    hasher.write_f64(options.scale);
+    for v in options.transform.to_cols_array() {
+        hasher.write_f64(v);
+    }
+    hasher.write_u64(match options.normals {
+        NormalMode::Flat => 0,
+        NormalMode::Smooth => 1,
+        NormalMode::Recompute => 2,
+    });
+
+    hasher.write_u64(options.material.is_some() as u64);
+    if options.material.is_none() {
+        for library in material_libraries(path, &source) {
+            hasher.write_bytes(library.to_string_lossy().as_bytes());
+            // 找不到的 MTL 文件与空文件区分开，文件出现后会换一个缓存
+            match fs::read(&library) {
+                Ok(bytes) => {
+                    hasher.write_u64(1);
+                    hasher.write_bytes(&bytes);
+                }
+                Err(_) => hasher.write_u64(0),
+            }
+        }
+    }
+
+    // 重建阈值只影响重拟合，不参与哈希
+    let bvh = &options.bvh;
+    match bvh.split {
+        SplitMethod::Median => hasher.write_u64(0),
+        SplitMethod::Sah { bins } => {
+            hasher.write_u64(1);
+            hasher.write_u64(bins as u64);
+        }
+        SplitMethod::Lbvh { treelet_rounds } => {
+            hasher.write_u64(2);
+            hasher.write_u64(treelet_rounds as u64);
+        }
+    }
+    hasher.write_u64(bvh.max_leaf_size as u64);
+    Ok(hasher.finish())
+}
+
+/// 缓存的内容：`TriangleMesh` 除材质以外的全部数据
+struct CachedMesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<[f64; 2]>,
+    indices: Vec<[u32; 3]>,
+    face_materials: Vec<u32>,
+    nodes: Vec<FlatNode>,
+    stats: BvhStats,
+}
+
+impl CachedMesh {
+    /// 检查下标后与 `materials` 组装成网格，损坏的数据返回 `InvalidData`
+    fn into_mesh(self, materials: Vec<MaterialPtr>) -> io::Result<TriangleMesh> {
+        let bad = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        if (!self.normals.is_empty() && self.normals.len() != self.positions.len())
+            || (!self.uvs.is_empty() && self.uvs.len() != self.positions.len())
+        {
+            return Err(bad("vertex attribute count mismatch"));
+        }
+        if self
+            .indices
+            .iter()
+            .flatten()
+            .any(|&i| i as usize >= self.positions.len())
+        {
+            return Err(bad("triangle index out of range"));
+        }
+        // MTL 文件改变时哈希已经不同，这里防止损坏的文件越界
+        if materials.is_empty()
+            || (!self.face_materials.is_empty()
+                && (self.face_materials.len() != self.indices.len()
+                    || self
+                        .face_materials
+                        .iter()
+                        .any(|&m| m as usize >= materials.len())))
+        {
+            return Err(bad("material index out of range"));
+        }
+
+        Ok(TriangleMesh::from_parts(
+            self.positions,
+            self.normals,
+            self.uvs,
+            self.indices,
+            materials,
+            self.face_materials,
+            (self.nodes, self.stats),
+        ))
+    }
+
+    /// 先写临时文件再重命名，与断点文件相同
+    fn save(mesh: &TriangleMesh, path: &Path, key: u64) -> io::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut out = BufWriter::new(File::create(&tmp_path)?);
+            out.write_all(MAGIC)?;
+            out.write_all(&VERSION.to_le_bytes())?;
+            out.write_all(&key.to_le_bytes())?;
+
+            let stats = mesh.stats();
+            for n in [
+                stats.interior_nodes,
+                stats.leaves,
+                stats.primitives,
+                stats.max_depth,
+            ] {
+                out.write_all(&(n as u64).to_le_bytes())?;
+            }
+            out.write_all(&stats.sah_cost.to_le_bytes())?;
+            out.write_all(&(stats.build_time.as_nanos() as u64).to_le_bytes())?;
+
+            for vectors in [mesh.positions(), mesh.normals()] {
+                out.write_all(&(vectors.len() as u64).to_le_bytes())?;
+                for v in vectors {
+                    out.write_all(&v.x().to_le_bytes())?;
+                    out.write_all(&v.y().to_le_bytes())?;
+                    out.write_all(&v.z().to_le_bytes())?;
+                }
+            }
+
+            out.write_all(&(mesh.uvs().len() as u64).to_le_bytes())?;
+            for uv in mesh.uvs().iter().flatten() {
+                out.write_all(&uv.to_le_bytes())?;
+            }
+
+            out.write_all(&(mesh.indices().len() as u64).to_le_bytes())?;
+            for i in mesh.indices().iter().flatten() {
+                out.write_all(&i.to_le_bytes())?;
+            }
+
+            out.write_all(&(mesh.face_materials().len() as u64).to_le_bytes())?;
+            for m in mesh.face_materials() {
+                out.write_all(&m.to_le_bytes())?;
+            }
+
+            out.write_all(&(mesh.nodes().len() as u64).to_le_bytes())?;
+            for node in mesh.nodes() {
+                for axis in 0..3 {
+                    let interval = node.bbox.axis_interval(axis);
+                    out.write_all(&interval.min.to_le_bytes())?;
+                    out.write_all(&interval.max.to_le_bytes())?;
+                }
+                out.write_all(&node.offset.to_le_bytes())?;
+                out.write_all(&node.count.to_le_bytes())?;
+                out.write_all(&(node.axis as u32).to_le_bytes())?;
+            }
+            out.flush()?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+
+    /// 读取缓存文件，格式或哈希不符时返回 `InvalidData`
+    fn load(path: &Path, key: u64) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut input = bytes.as_slice();
+        let bad = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        if input.len() < MAGIC.len() || &input[..MAGIC.len()] != MAGIC {
+            return Err(bad("wrong magic number"));
+        }
+        input = &input[MAGIC.len()..];
+        if read_u32(&mut input)? != VERSION {
+            return Err(bad("unsupported version"));
+        }
+        if read_u64(&mut input)? != key {
+            return Err(bad("source or load options changed"));
+        }
+
+        let stats = BvhStats {
+            interior_nodes: read_u64(&mut input)? as usize,
+            leaves: read_u64(&mut input)? as usize,
+            primitives: read_u64(&mut input)? as usize,
+            max_depth: read_u64(&mut input)? as usize,
+            sah_cost: read_f64(&mut input)?,
+            build_time: Duration::from_nanos(read_u64(&mut input)?),
+        };
+
+        // 先按剩余字节数检查数量，避免损坏的文件导致巨大的分配
+        let read_count = |input: &mut &[u8], item_size: usize| -> io::Result<usize> {
+            let count = read_u64(input)? as usize;
+            if count > input.len() / item_size {
+                return Err(bad("truncated mesh data"));
+            }
+            Ok(count)
+        };
+        let read_vectors = |input: &mut &[u8]| -> io::Result<Vec<Vec3>> {
+            let count = read_count(input, 3 * 8)?;
+            (0..count)
+                .map(|_| {
+                    Ok(Vec3::new(
+                        read_f64(input)?,
+                        read_f64(input)?,
+                        read_f64(input)?,
+                    ))
+                })
+                .collect()
+        };
+
+        let positions = read_vectors(&mut input)?;
+        let normals = read_vectors(&mut input)?;
+
+        let uv_count = read_count(&mut input, 2 * 8)?;
+        let uvs = (0..uv_count)
+            .map(|_| Ok([read_f64(&mut input)?, read_f64(&mut input)?]))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let index_count = read_count(&mut input, 3 * 4)?;
+        let indices = (0..index_count)
+            .map(|_| {
+                Ok([
+                    read_u32(&mut input)?,
+                    read_u32(&mut input)?,
+                    read_u32(&mut input)?,
+                ])
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let material_count = read_count(&mut input, 4)?;
+        let face_materials = (0..material_count)
+            .map(|_| read_u32(&mut input))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let node_count = read_count(&mut input, 6 * 8 + 3 * 4)?;
+        let mut nodes = Vec::with_capacity(node_count);
+        for _ in 0..node_count {
+            let mut intervals = [Interval::EMPTY; 3];
+            for interval in &mut intervals {
+                *interval = Interval::new(read_f64(&mut input)?, read_f64(&mut input)?);
+            }
+            let [x, y, z] = intervals;
+            nodes.push(FlatNode {
+                bbox: Aabb::from_intervals(x, y, z),
+                offset: read_u32(&mut input)?,
+                count: read_u32(&mut input)?,
+                axis: read_u32(&mut input)? as u8,
+            });
+        }
+
+        // 下标越界的节点会在遍历时崩溃，读取时就拒绝
+        let valid = nodes.iter().enumerate().all(|(i, node)| {
+            if node.is_leaf() {
+                node.offset as usize + node.count as usize <= indices.len()
+            } else {
+                node.offset as usize > i + 1
+                    && (node.offset as usize) < nodes.len()
+                    && node.axis < 3
+            }
+        });
+        if nodes.is_empty() || !valid {
+            return Err(bad("invalid node data"));
+        }
+
+        Ok(Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            face_materials,
+            nodes,
+            stats,
+        })
+    }
+}
@@ -0,0 +1,90 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, MaterialPtr, Metal};
+use crate::texture::{ImageTexture, SolidColor};
+
+/// MTL 中没有给出漫反射颜色时使用的反照率，与 MTL 规范的默认值相同
+const DEFAULT_DIFFUSE: f64 = 0.8;
+
+/// 没有折射率或折射率不大于 1 时透明材质使用的折射率（玻璃）
+const DEFAULT_REFRACTION_INDEX: f64 = 1.5;
+
+/// 没有材质或材质库加载失败的面使用的材质
+pub fn default_material() -> MaterialPtr {
+    Arc::new(Lambertian::new(Color::new(
+        DEFAULT_DIFFUSE,
+        DEFAULT_DIFFUSE,
+        DEFAULT_DIFFUSE,
+    )))
+}
+
+/// 把 `tobj::load_obj` 返回的材质库转换为材质列表，下标与 `material_id` 一致
+///
+/// 贴图路径相对于 OBJ 文件所在的目录。材质库加载失败时记录警告并返回空列表，
+/// 引用不到材质的面使用 `default_material`
+pub fn load_materials(
+    materials: Result<Vec<tobj::Material>, tobj::LoadError>,
+    obj_path: &Path,
+) -> Vec<MaterialPtr> {
+    let base_dir = obj_path.parent().unwrap_or(Path::new(""));
+    match materials {
+        Ok(materials) => materials
+            .iter()
+            .map(|m| material_from_mtl(m, base_dir))
+            .collect(),
+        Err(e) => {
+            log::warn!("Failed to load materials for {}: {}", obj_path.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+/// 按 MTL 参数选择最接近的材质
+///
+/// 依次判断：`Ke` 不为零为 `DiffuseLight`；`d` 小于 1 为折射率 `Ni` 的 `Dielectric`；
+/// `Ks` 比 `Kd` 亮为 `Metal`，`Ns` 越小越模糊；其余为 `Lambertian`，有 `map_Kd` 时使用图片纹理
+pub fn material_from_mtl(m: &tobj::Material, base_dir: &Path) -> MaterialPtr {
+    let color = |c: [f32; 3]| Color::new(c[0] as f64, c[1] as f64, c[2] as f64);
+    let brightness = |c: Option<[f32; 3]>| c.map_or(0.0, |c| c[0].max(c[1]).max(c[2]));
+
+    if let Some(emissive) = m.emissive.filter(|&e| brightness(Some(e)) > 0.0) {
+        return Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(color(
+            emissive,
+        )))));
+    }
+
+    if m.dissolve.is_some_and(|d| d < 1.0) {
+        let ri = m
+            .optical_density
+            .map(|ni| ni as f64)
+            .filter(|&ni| ni > 1.0)
+            .unwrap_or(DEFAULT_REFRACTION_INDEX);
+        return Arc::new(Dielectric::new(ri));
+    }
+
+    if let Some(specular) = m
+        .specular
+        .filter(|_| brightness(m.specular) > brightness(m.diffuse))
+    {
+        // Phong 指数换算为粗糙度：Ns 为 0 时完全模糊，越大越接近镜面
+        let fuzz = m
+            .shininess
+            .map_or(1.0, |ns| (2.0 / (ns.max(0.0) as f64 + 2.0)).sqrt());
+        return Arc::new(Metal::new(color(specular), fuzz));
+    }
+
+    if let Some(texture) = &m.diffuse_texture {
+        let path = base_dir.join(texture);
+        return Arc::new(Lambertian::from_texture(Arc::new(ImageTexture::new(
+            &path.to_string_lossy(),
+        ))));
+    }
+
+    let albedo = m.diffuse.map_or(
+        Color::new(DEFAULT_DIFFUSE, DEFAULT_DIFFUSE, DEFAULT_DIFFUSE),
+        color,
+    );
+    Arc::new(Lambertian::new(albedo))
+}
@@ -1,106 +1,361 @@
-use crate::{
-    hittable::{HitRecord, Hittable},
-    interval::Interval,
-    material::Material,
-    ray::Ray,
-    triangle::Triangle,
-    vec3::Point3,
-};
+use glam::DAffine3;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::{error::Error, path::Path};
-use tobj::{LoadOptions, load_obj};
+use tobj::LoadOptions;
 
-#[derive(Debug)]
-pub struct ObjModel {
-    pub triangles: Vec<Arc<dyn Hittable + Send + Sync>>,
-    pub bbox_min: Point3,
-    pub bbox_max: Point3,
-}
+use crate::bvh::BvhOptions;
+use crate::hittable::Hittable;
+use crate::instance::{from_dvec3, to_dvec3};
+use crate::material::MaterialPtr;
+use crate::mtl::{default_material, load_materials};
+use crate::triangle::Triangle;
+use crate::triangle_mesh::{TriangleMesh, vertex_normals};
+use crate::vec3::{Point3, Vec3, unit_vector};
 
-impl ObjModel {
-    pub fn load<P: AsRef<Path>>(
-        path: P,
-        material: Arc<dyn Material + Send + Sync>,
-        scale: f64,
-        offset: Point3,
-    ) -> Result<Self, Box<dyn Error>> {
-        let obj = load_obj(
-            path.as_ref(),
-            &LoadOptions {
-                triangulate: true,
-                single_index: true,
-                ..Default::default()
-            },
-        )?;
-
-        let (models, _) = obj;
-        let mut triangles: Vec<Arc<dyn Hittable + Send + Sync>> = Vec::new();
-        let mut bbox_min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
-        let mut bbox_max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
-
-        for model in models {
-            let mesh = model.mesh;
-            let indices = mesh.indices;
-            let positions = mesh.positions;
-
-            for chunk in indices.chunks(3) {
-                let i0 = chunk[0] as usize * 3;
-                let i1 = chunk[1] as usize * 3;
-                let i2 = chunk[2] as usize * 3;
-
-                let v0 = Point3::new(
-                    positions[i0] as f64 * scale + offset.x(),
-                    positions[i0 + 1] as f64 * scale + offset.y(),
-                    positions[i0 + 2] as f64 * scale + offset.z(),
-                );
+/// 顶点法向的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NormalMode {
+    /// 不使用顶点法向，每个面都是平的
+    Flat,
+    /// 使用文件中的顶点法向，有模型缺少时按夹角加权重新计算（见 `vertex_normals`）
+    #[default]
+    Smooth,
+    /// 忽略文件中的顶点法向，总是重新计算
+    Recompute,
+}
 
-                let v1 = Point3::new(
-                    positions[i1] as f64 * scale + offset.x(),
-                    positions[i1 + 1] as f64 * scale + offset.y(),
-                    positions[i1 + 2] as f64 * scale + offset.z(),
-                );
+/// OBJ 模型的加载参数
+#[derive(Debug, Clone)]
+pub struct ObjOptions {
+    pub scale: f64,          // 顶点先乘以 scale
+    pub transform: DAffine3, // 再做仿射变换，法向随之变换
+    pub normals: NormalMode,
+    /// 为 `None` 时按 MTL 文件逐面指定材质（见 `mtl::material_from_mtl`），否则所有面都使用它
+    pub material: Option<MaterialPtr>,
+    pub bvh: BvhOptions,
+}
 
-                let v2 = Point3::new(
-                    positions[i2] as f64 * scale + offset.x(),
-                    positions[i2 + 1] as f64 * scale + offset.y(),
-                    positions[i2 + 2] as f64 * scale + offset.z(),
-                );
+impl Default for ObjOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            transform: DAffine3::IDENTITY,
+            normals: NormalMode::default(),
+            material: None,
+            bvh: TriangleMesh::default_options(),
+        }
+    }
+}
 
-                // 更新包围盒
-                bbox_min = bbox_min.min(v0).min(v1).min(v2);
-                bbox_max = bbox_max.max(v0).max(v1).max(v2);
+/// 加载 OBJ 模型时的错误
+#[derive(Debug)]
+pub enum ObjError {
+    Load {
+        path: PathBuf,
+        source: tobj::LoadError,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Empty {
+        path: PathBuf,
+    },
+    IndexOutOfRange {
+        path: PathBuf,
+        index: u32,
+        vertices: usize,
+    },
+}
 
-                let triangle = Triangle::new(v0, v1, v2, material.clone());
-                triangles.push(Arc::new(triangle) as Arc<dyn Hittable + Send + Sync>);
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Load { path, source } => {
+                write!(f, "failed to load {}: {}", path.display(), source)
+            }
+            ObjError::Io { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
             }
+            ObjError::Empty { path } => write!(f, "{} contains no triangles", path.display()),
+            ObjError::IndexOutOfRange {
+                path,
+                index,
+                vertices,
+            } => write!(
+                f,
+                "{} references vertex {} but has only {} vertices",
+                path.display(),
+                index,
+                vertices
+            ),
         }
+    }
+}
 
-        Ok(Self {
-            triangles,
-            bbox_min,
-            bbox_max,
-        })
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Load { source, .. } => Some(source),
+            ObjError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// 加载 OBJ 模型并构建 `TriangleMesh`
+pub fn load_obj(path: impl AsRef<Path>, options: &ObjOptions) -> Result<TriangleMesh, ObjError> {
+    Ok(ObjData::load(path, options)?.into_mesh(options.bvh))
+}
+
+/// OBJ 文件内容中的 `mtllib` 行，按字节查找，其余行不要求是 UTF-8
+fn mtllib_lines(source: &[u8]) -> impl Iterator<Item = String> + '_ {
+    source
+        .split(|&b| b == b'\n')
+        .map(|line| String::from_utf8_lossy(line.trim_ascii()).into_owned())
+        .filter(|line| line.starts_with("mtllib "))
+}
+
+/// OBJ 文件内容 `source` 中 `mtllib` 引用的材质库，路径相对于 OBJ 文件所在的目录（与 tobj 相同）
+pub fn material_libraries(obj_path: &Path, source: &[u8]) -> Vec<PathBuf> {
+    let base_dir = obj_path.parent().unwrap_or(Path::new(""));
+    mtllib_lines(source)
+        .map(|line| base_dir.join(line["mtllib ".len()..].trim()))
+        .collect()
+}
+
+/// 由 tobj 读到的材质库得到网格的材质列表与无材质的面使用的下标
+///
+/// 指定了 `options.material` 时只有它一个材质；否则最后一个材质留给没有材质的面
+fn mesh_materials(
+    mtl_materials: Result<Vec<tobj::Material>, tobj::LoadError>,
+    path: &Path,
+    options: &ObjOptions,
+) -> (Vec<MaterialPtr>, Option<u32>) {
+    match &options.material {
+        Some(material) => (vec![material.clone()], None),
+        None => {
+            let mut materials = load_materials(mtl_materials, path);
+            materials.push(default_material());
+            let default_id = (materials.len() - 1) as u32;
+            (materials, Some(default_id))
+        }
     }
 }
 
-impl Hittable for ObjModel {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let mut hit_anything = false;
-        let mut closest_so_far = ray_t.max;
-
-        for triangle in &self.triangles {
-            let mut temp_rec = HitRecord::default();
-            if triangle.hit(r, Interval::new(ray_t.min, closest_so_far), &mut temp_rec) {
-                hit_anything = true;
-                closest_so_far = temp_rec.t;
-                *rec = temp_rec;
+/// 解析并变换后的 OBJ 数据：文件中所有模型合并到同一套顶点缓冲中
+///
+/// 通常直接用 `load_obj` 得到网格；需要逐面的 `Triangle`（比较不同 BVH 布局）时使用这里的数据
+#[derive(Debug)]
+pub struct ObjData {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>, // 为空表示平面着色
+    pub uvs: Vec<[f64; 2]>, // 为空表示没有纹理坐标
+    pub indices: Vec<[u32; 3]>,
+    pub materials: Vec<MaterialPtr>,
+    pub face_materials: Vec<u32>, // 各三角形在 materials 中的下标，为空表示都用第一个
+}
+
+impl ObjData {
+    pub fn load(path: impl AsRef<Path>, options: &ObjOptions) -> Result<Self, ObjError> {
+        let path = path.as_ref();
+        let load_options = LoadOptions {
+            triangulate: true,
+            single_index: true, // 位置、法向和纹理坐标共用一套下标
+            ..Default::default()
+        };
+        let (models, mtl_materials) =
+            tobj::load_obj(path, &load_options).map_err(|source| ObjError::Load {
+                path: path.to_path_buf(),
+                source,
+            })?;
+
+        let (materials, default_id) = mesh_materials(mtl_materials, path, options);
+
+        // 法向用线性部分的逆转置变换，缩放为负时同样成立
+        let linear = options.transform.matrix3 * options.scale;
+        let normal_matrix = linear.inverse().transpose();
+
+        let mut data = Self {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+            materials,
+            face_materials: Vec::new(),
+        };
+        let mut has_normals = options.normals == NormalMode::Smooth;
+        // 没有纹理坐标的模型，有其他模型带纹理坐标时它们的顶点取 (0, 0)
+        let mut missing_uvs = Vec::new();
+
+        for model in &models {
+            let mesh = &model.mesh;
+            let base = data.positions.len() as u32;
+            let vertices = mesh.positions.len() / 3;
+            log::debug!(
+                "  model {}: {} vertices, {} triangles",
+                model.name,
+                vertices,
+                mesh.indices.len() / 3
+            );
+
+            if let Some(&index) = mesh.indices.iter().find(|&&i| i as usize >= vertices) {
+                return Err(ObjError::IndexOutOfRange {
+                    path: path.to_path_buf(),
+                    index,
+                    vertices,
+                });
+            }
+
+            data.positions
+                .extend(mesh.positions.chunks_exact(3).map(|p| {
+                    let p = Point3::new(p[0] as f64, p[1] as f64, p[2] as f64);
+                    from_dvec3(
+                        options
+                            .transform
+                            .transform_point3(to_dvec3(&(options.scale * p))),
+                    )
+                }));
+            has_normals &= !mesh.normals.is_empty();
+            if has_normals {
+                data.normals.extend(mesh.normals.chunks_exact(3).map(|n| {
+                    let n = Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64);
+                    unit_vector(from_dvec3(normal_matrix * to_dvec3(&n)))
+                }));
+            }
+            if mesh.texcoords.is_empty() {
+                missing_uvs.push(model.name.as_str());
+                data.uvs.resize(data.positions.len(), [0.0, 0.0]);
+            } else {
+                data.uvs.extend(
+                    mesh.texcoords
+                        .chunks_exact(2)
+                        .map(|t| [t[0] as f64, t[1] as f64]),
+                );
+            }
+            data.indices.extend(
+                mesh.indices
+                    .chunks_exact(3)
+                    .map(|f| [base + f[0], base + f[1], base + f[2]]),
+            );
+            if let Some(default_id) = default_id {
+                let id = mesh
+                    .material_id
+                    .map(|id| id as u32)
+                    .filter(|&id| id < default_id)
+                    .unwrap_or(default_id);
+                data.face_materials.resize(data.indices.len(), id);
             }
         }
 
-        hit_anything
+        if data.indices.is_empty() {
+            return Err(ObjError::Empty {
+                path: path.to_path_buf(),
+            });
+        }
+
+        data.normals = match options.normals {
+            NormalMode::Flat => Vec::new(),
+            NormalMode::Smooth if has_normals => data.normals,
+            _ => vertex_normals(&data.positions, &data.indices),
+        };
+        if missing_uvs.len() == models.len() {
+            data.uvs.clear();
+        } else if !missing_uvs.is_empty() {
+            log::warn!(
+                "{}: models without texture coordinates use (0, 0): {}",
+                path.display(),
+                missing_uvs.join(", ")
+            );
+        }
+        // 逐个模型追加时预留的容量可能接近实际大小的两倍
+        data.positions.shrink_to_fit();
+        data.normals.shrink_to_fit();
+        data.uvs.shrink_to_fit();
+
+        log::info!(
+            "Loaded {}: {} triangles, {} vertices, {} materials",
+            path.display(),
+            data.indices.len(),
+            data.positions.len(),
+            data.materials.len()
+        );
+        Ok(data)
+    }
+
+    /// 只加载 `load` 会得到的材质列表，不解析几何（`MeshCache` 命中时使用）
+    ///
+    /// 只把 OBJ 中的 `mtllib` 行交给 tobj，材质的顺序与合并规则与完整加载时相同
+    pub fn load_materials(
+        path: impl AsRef<Path>,
+        options: &ObjOptions,
+    ) -> Result<Vec<MaterialPtr>, ObjError> {
+        let path = path.as_ref();
+        if options.material.is_some() {
+            return Ok(mesh_materials(Ok(Vec::new()), path, options).0);
+        }
+
+        let source = fs::read(path).map_err(|source| ObjError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let libraries: String = mtllib_lines(&source).map(|line| line + "\n").collect();
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        let (_, mtl_materials) = tobj::load_obj_buf(
+            &mut libraries.as_bytes(),
+            &LoadOptions::default(),
+            |mtl_path| tobj::load_mtl(base_dir.join(mtl_path)),
+        )
+        .map_err(|source| ObjError::Load {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(mesh_materials(mtl_materials, path, options).0)
+    }
+
+    /// 构建共享顶点的网格
+    pub fn into_mesh(self, options: BvhOptions) -> TriangleMesh {
+        let mesh = TriangleMesh::with_materials(
+            self.positions,
+            self.normals,
+            self.uvs,
+            self.indices,
+            self.materials,
+            self.face_materials,
+            options,
+        );
+        log::info!("BVH: {}", mesh.stats());
+        mesh
+    }
+
+    /// 每个面一个独立的 `Triangle`，可以放进任意 BVH 布局
+    pub fn triangles(&self) -> Vec<Arc<dyn Hittable + Send + Sync>> {
+        self.indices
+            .iter()
+            .enumerate()
+            .map(|(k, face)| {
+                let [v0, v1, v2] = face.map(|i| self.positions[i as usize]);
+                let material = self.face_materials.get(k).map_or(0, |&m| m as usize);
+                let mut triangle = Triangle::new(v0, v1, v2, self.materials[material].clone());
+                if !self.normals.is_empty() {
+                    triangle = triangle.with_normals(face.map(|i| self.normals[i as usize]));
+                }
+                if !self.uvs.is_empty() {
+                    triangle = triangle.with_uvs(face.map(|i| self.uvs[i as usize]));
+                }
+                Arc::new(triangle) as Arc<dyn Hittable + Send + Sync>
+            })
+            .collect()
     }
 
-    fn bounding_box(&self) -> crate::aabb::Aabb {
-        crate::aabb::Aabb::from_points(self.bbox_min, self.bbox_max)
+    /// 各三角形的顶点
+    pub fn faces(&self) -> Vec<[Point3; 3]> {
+        self.indices
+            .iter()
+            .map(|face| face.map(|i| self.positions[i as usize]))
+            .collect()
     }
 }
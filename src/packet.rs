@@ -0,0 +1,232 @@
+use glam::DVec4;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// 每组的射线数，即 `DVec4` 的通道数
+pub const LANES: usize = 4;
+
+/// 一束射线最多包含的射线数，活动射线用 u64 掩码表示（8×8 像素块）
+pub const MAX_PACKET_SIZE: usize = 64;
+
+/// 四条射线按分量存放（SoA），一次运算处理四条射线
+#[derive(Debug, Clone, Copy)]
+pub struct RayLanes {
+    pub origin: [DVec4; 3],
+    pub direction: [DVec4; 3],
+    pub inv_dir: [DVec4; 3],
+}
+
+/// 一束相干射线，例如相邻像素的主射线
+///
+/// 射线按四条一组存放，BVH 遍历时以四通道测试同一个包围盒，图元也可以逐组求交
+/// （见 `Hittable::hit_packet`）。最后一组不满四条时用最后一条射线补齐，补齐的通道不会被激活
+#[derive(Debug, Clone)]
+pub struct RayPacket {
+    rays: Vec<Ray>,
+    lanes: Vec<RayLanes>,
+    coherent: bool, // 所有射线的方向在各轴上符号相同
+}
+
+impl RayPacket {
+    pub fn new(rays: Vec<Ray>) -> Self {
+        assert!(
+            rays.len() <= MAX_PACKET_SIZE,
+            "a ray packet holds at most {} rays",
+            MAX_PACKET_SIZE
+        );
+
+        let lanes = rays
+            .chunks(LANES)
+            .map(|chunk| {
+                let ray = |k: usize| &chunk[k.min(chunk.len() - 1)];
+                let gather = |f: &dyn Fn(&Ray) -> f64| {
+                    DVec4::new(f(ray(0)), f(ray(1)), f(ray(2)), f(ray(3)))
+                };
+                let direction = [
+                    gather(&|r| r.direction().x()),
+                    gather(&|r| r.direction().y()),
+                    gather(&|r| r.direction().z()),
+                ];
+                RayLanes {
+                    origin: [
+                        gather(&|r| r.origin().x()),
+                        gather(&|r| r.origin().y()),
+                        gather(&|r| r.origin().z()),
+                    ],
+                    direction,
+                    inv_dir: direction.map(|d| DVec4::ONE / d),
+                }
+            })
+            .collect();
+
+        let signs = |r: &Ray| {
+            let d = r.direction();
+            [d.x() < 0.0, d.y() < 0.0, d.z() < 0.0]
+        };
+        let coherent = rays.windows(2).all(|w| signs(&w[0]) == signs(&w[1]));
+
+        Self {
+            rays,
+            lanes,
+            coherent,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rays.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rays.is_empty()
+    }
+
+    pub fn rays(&self) -> &[Ray] {
+        &self.rays
+    }
+
+    pub fn lanes(&self) -> &[RayLanes] {
+        &self.lanes
+    }
+
+    /// 方向符号一致的射线束遍历 BVH 时可以共用同一个近侧子节点
+    pub fn is_coherent(&self) -> bool {
+        self.coherent
+    }
+
+    /// 第一条射线的方向在各轴上是否为负
+    pub fn direction_is_negative(&self) -> [bool; 3] {
+        let d = self.rays[0].direction();
+        [d.x() < 0.0, d.y() < 0.0, d.z() < 0.0]
+    }
+
+    /// 包含全部射线的掩码
+    pub fn full_mask(&self) -> u64 {
+        if self.rays.len() == MAX_PACKET_SIZE {
+            u64::MAX
+        } else {
+            (1 << self.rays.len()) - 1
+        }
+    }
+
+    /// 四通道 slab 测试，返回 `active` 中与包围盒在 (t_min, hits.t_max) 内相交的射线
+    pub fn hit_box(&self, bbox: &Aabb, active: u64, hits: &PacketHits) -> u64 {
+        let mut result = 0;
+        for (g, lanes) in self.lanes.iter().enumerate() {
+            let group = group_mask(active, g);
+            if group == 0 {
+                continue;
+            }
+
+            let mut near = DVec4::splat(hits.t_min);
+            let mut far = hits.t_max_lanes(g);
+            for axis in 0..3 {
+                let ax = bbox.axis_interval(axis);
+                let t0 = (DVec4::splat(ax.min) - lanes.origin[axis]) * lanes.inv_dir[axis];
+                let t1 = (DVec4::splat(ax.max) - lanes.origin[axis]) * lanes.inv_dir[axis];
+                near = near.max(t0.min(t1));
+                far = far.min(t0.max(t1));
+            }
+
+            result |= (near.cmplt(far).bitmask() as u64 & group) << (g * LANES);
+        }
+        result
+    }
+}
+
+/// 一束射线的求交结果，各数组按组补齐到 `LANES` 的整数倍
+#[derive(Debug, Clone)]
+pub struct PacketHits {
+    pub t_min: f64,
+    pub t_max: Vec<f64>, // 各射线当前最近交点的 t，初始为区间上限
+    pub recs: Vec<HitRecord>,
+    pub hit: u64, // 已击中物体的射线
+}
+
+impl PacketHits {
+    pub fn new(packet: &RayPacket, ray_t: Interval) -> Self {
+        let padded = packet.lanes.len() * LANES;
+        Self {
+            t_min: ray_t.min,
+            t_max: vec![ray_t.max; padded],
+            recs: vec![HitRecord::default(); padded],
+            hit: 0,
+        }
+    }
+
+    pub fn is_hit(&self, k: usize) -> bool {
+        self.hit & (1 << k) != 0
+    }
+
+    /// 第 k 条射线当前的求交区间
+    pub fn interval(&self, k: usize) -> Interval {
+        Interval::new(self.t_min, self.t_max[k])
+    }
+
+    /// 记录第 k 条射线在 `rec` 处的更近交点
+    pub fn record(&mut self, k: usize) {
+        self.t_max[k] = self.recs[k].t;
+        self.hit |= 1 << k;
+    }
+
+    /// 第 g 组射线的 t 上限
+    pub fn t_max_lanes(&self, g: usize) -> DVec4 {
+        DVec4::from_slice(&self.t_max[g * LANES..(g + 1) * LANES])
+    }
+}
+
+/// 第 g 组在掩码中对应的 4 位
+pub fn group_mask(mask: u64, g: usize) -> u64 {
+    (mask >> (g * LANES)) & ((1 << LANES) - 1)
+}
+
+/// 掩码中各射线的下标
+pub fn active_rays(mut mask: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if mask == 0 {
+            return None;
+        }
+        let k = mask.trailing_zeros() as usize;
+        mask &= mask - 1;
+        Some(k)
+    })
+}
+
+/// 逐条射线调用 `hit`，用于不支持或不适合成束求交的物体与射线
+pub fn hit_each<H: Hittable + ?Sized>(
+    object: &H,
+    packet: &RayPacket,
+    active: u64,
+    hits: &mut PacketHits,
+) {
+    for k in active_rays(active) {
+        let ray_t = hits.interval(k);
+        if object.hit(&packet.rays[k], ray_t, &mut hits.recs[k]) {
+            hits.record(k);
+        }
+    }
+}
+
+/// 把向量广播到四个通道
+pub fn splat(v: &Vec3) -> [DVec4; 3] {
+    [
+        DVec4::splat(v.x()),
+        DVec4::splat(v.y()),
+        DVec4::splat(v.z()),
+    ]
+}
+
+pub fn dot_lanes(a: &[DVec4; 3], b: &[DVec4; 3]) -> DVec4 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross_lanes(a: &[DVec4; 3], b: &[DVec4; 3]) -> [DVec4; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
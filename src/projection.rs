@@ -0,0 +1,86 @@
+use crate::rtweekend::{PI, degrees_to_radians};
+use crate::vec3::Vec3;
+
+/// 相机的投影方式
+///
+/// 所有投影都使用相机的 `lookfrom`/`lookat`/`vup` 坐标系，
+/// 局部坐标中 x 向右、y 向上、-z 为观察方向
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// 透视投影（针孔 / 薄透镜），视角由 `vfov` 决定
+    Perspective,
+    /// 正交投影，`height` 为视口在世界空间中的高度，宽度由宽高比决定
+    Orthographic { height: f64 },
+    /// 360°×180° 等距柱状投影，图像宽高比应为 2:1
+    Equirectangular,
+    /// 鱼眼投影，`fov` 为图像圆直径对应的视角（度），可超过 180°
+    Fisheye { fov: f64, mapping: FisheyeMapping },
+}
+
+/// 鱼眼镜头的映射函数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FisheyeMapping {
+    /// 等距投影：r ∝ θ
+    Equidistant,
+    /// 等立体角投影：r ∝ 2·sin(θ/2)
+    Equisolid,
+}
+
+impl Projection {
+    /// 是否需要使用视口平面（透视与正交投影）
+    pub fn uses_viewport(&self) -> bool {
+        matches!(
+            self,
+            Projection::Perspective | Projection::Orthographic { .. }
+        )
+    }
+
+    /// 计算全景类投影在相机局部坐标中的射线方向
+    ///
+    /// - `sx`, `sy`: 图像上的归一化坐标，范围 [0,1]，原点在左上角
+    /// - `aspect`: 图像宽高比
+    ///
+    /// 对鱼眼投影，图像圆外的点返回 `None`
+    pub fn local_direction(&self, sx: f64, sy: f64, aspect: f64) -> Option<Vec3> {
+        match *self {
+            Projection::Equirectangular => {
+                let phi = (sx - 0.5) * 2.0 * PI; // 经度，0 为正前方
+                let theta = (0.5 - sy) * PI; // 纬度
+                Some(Vec3::new(
+                    theta.cos() * phi.sin(),
+                    theta.sin(),
+                    -theta.cos() * phi.cos(),
+                ))
+            }
+            Projection::Fisheye { fov, mapping } => {
+                // 以较短边为图像圆直径
+                let (scale_x, scale_y) = if aspect >= 1.0 {
+                    (aspect, 1.0)
+                } else {
+                    (1.0, 1.0 / aspect)
+                };
+                let px = (2.0 * sx - 1.0) * scale_x;
+                let py = (1.0 - 2.0 * sy) * scale_y;
+                let r = (px * px + py * py).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+
+                let half_fov = degrees_to_radians(fov) / 2.0;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => {
+                        2.0 * (r * (half_fov / 2.0).sin()).clamp(-1.0, 1.0).asin()
+                    }
+                };
+                let psi = py.atan2(px);
+                Some(Vec3::new(
+                    theta.sin() * psi.cos(),
+                    theta.sin() * psi.sin(),
+                    -theta.cos(),
+                ))
+            }
+            Projection::Perspective | Projection::Orthographic { .. } => None,
+        }
+    }
+}
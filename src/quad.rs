@@ -2,12 +2,14 @@ use std::sync::Arc;
 
 use crate::{
     aabb::Aabb,
+    checkpoint::SceneHasher,
     hittable::{HitRecord, Hittable},
     hittable_list::HittableList,
     interval::Interval,
     material::Material,
     ray::Ray,
     rtweekend::{INFINITY, random_double},
+    stats::count_primitive_tests,
     vec3::{Point3, Vec3, cross, dot, unit_vector},
 };
 
@@ -79,6 +81,7 @@ impl Hittable for Quad {
         ray_t: crate::interval::Interval,
         rec: &mut crate::hittable::HitRecord,
     ) -> bool {
+        count_primitive_tests(1);
         let denom = dot(&self.normal, &r.direction());
 
         if denom.abs() < 1e-8 {
@@ -114,6 +117,14 @@ impl Hittable for Quad {
         self.bbox
     }
 
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("quad");
+        hasher.write_vec3(&self.q);
+        hasher.write_vec3(&self.u);
+        hasher.write_vec3(&self.v);
+        hasher.write_material(self.mat.as_ref());
+    }
+
     fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
         let mut rec = HitRecord::default();
         if !self.hit(
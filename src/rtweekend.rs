@@ -1,11 +1,19 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
 use std::f64;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 // 常量定义
 pub const INFINITY: f64 = f64::INFINITY;
 pub const PI: f64 = 3.1415926535897932385;
 
+thread_local! {
+    // 每个线程独立的随机数生成器，可重新设定种子以便断点续渲时复现采样序列
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
 // 工具函数
 /// 将角度转换为弧度
 pub fn degrees_to_radians(degrees: f64) -> f64 {
@@ -13,17 +21,48 @@ pub fn degrees_to_radians(degrees: f64) -> f64 {
 }
 
 pub fn random_double() -> f64 {
-    rand::thread_rng().gen_range(0.0..1.0)
+    RNG.with(|rng| rng.borrow_mut().gen_range(0.0..1.0))
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
-    rand::thread_rng().gen_range(min..max)
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
+}
+
+/// 为当前线程的随机数生成器设定种子
+///
+/// 在构建场景前调用可使随机生成的场景（如 `final_scene`）每次运行都相同
+pub fn seed_thread_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// 将多个整数混合成一个种子（splitmix64）
+pub fn mix_seed(seed: u64, a: u64, b: u64) -> u64 {
+    let mut z = seed
+        .wrapping_add(a.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+        .wrapping_add(b.wrapping_mul(0xBF58_476D_1CE4_E5B9));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
 }
 
 pub fn random_int(min: i32, max: i32) -> i32 {
     random_double_range(min as f64, (max + 1) as f64) as i32
 }
 
+/// 在文件名（扩展名之前）追加 `_suffix`，如 `scene.ckpt` -> `scene_left.ckpt`
+pub fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut file_name = format!("{}_{}", stem, suffix);
+    if let Some(ext) = path.extension() {
+        file_name.push('.');
+        file_name.push_str(&ext.to_string_lossy());
+    }
+    path.with_file_name(file_name)
+}
+
 // 类型别名
 pub type SharedPtr<T> = Arc<T>;
 pub use self::SharedPtr as make_shared;
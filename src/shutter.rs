@@ -0,0 +1,49 @@
+/// 快门在开启区间内的透光曲线，决定射线时间的分布
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShutterCurve {
+    /// 理想快门：瞬间全开、瞬间全闭，时间均匀分布
+    Box,
+    /// 梯形：开启和关闭各用去快门时长的 `ramp` 比例（0 ~ 0.5）
+    Trapezoid { ramp: f64 },
+    /// 卷帘快门：逐行曝光，`readout` 为第一行到最后一行的起始时间差占快门时长的比例（0 ~ 1）
+    Rolling { readout: f64 },
+}
+
+impl ShutterCurve {
+    /// 在 [open, close] 内按快门曲线采样一个时刻
+    ///
+    /// - `row`: 扫描线在图像中的归一化位置（0 为顶行），仅卷帘快门使用
+    /// - `xi`: [0,1) 内的随机数
+    pub fn sample_time(&self, open: f64, close: f64, row: f64, xi: f64) -> f64 {
+        let duration = close - open;
+        let s = match *self {
+            ShutterCurve::Box => xi,
+            ShutterCurve::Trapezoid { ramp } => Self::sample_trapezoid(ramp.clamp(0.0, 0.5), xi),
+            ShutterCurve::Rolling { readout } => {
+                let readout = readout.clamp(0.0, 1.0);
+                readout * row + (1.0 - readout) * xi
+            }
+        };
+        open + s * duration
+    }
+
+    /// 对 [0,1] 上的梯形分布做逆变换采样
+    fn sample_trapezoid(ramp: f64, xi: f64) -> f64 {
+        if ramp <= 0.0 {
+            return xi;
+        }
+
+        // 梯形高度为 1 时总面积为 1 - ramp
+        let area = xi * (1.0 - ramp);
+        let ramp_area = ramp / 2.0;
+
+        if area < ramp_area {
+            (2.0 * area * ramp).sqrt()
+        } else if area < 1.0 - ramp - ramp_area {
+            ramp + (area - ramp_area)
+        } else {
+            let remaining = (1.0 - ramp - area).max(0.0);
+            1.0 - (2.0 * remaining * ramp).sqrt()
+        }
+    }
+}
@@ -1,17 +1,20 @@
 use crate::aabb::Aabb;
+use crate::checkpoint::SceneHasher;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
 use crate::material::MaterialPtr;
 use crate::onb::Onb;
 use crate::ray::Ray;
 use crate::rtweekend::{INFINITY, PI, random_double};
+use crate::stats::count_primitive_tests;
 use crate::vec3::{Point3, Vec3, dot};
 
 /// 表示三维空间中的球体
 #[derive(Debug)]
 pub struct Sphere {
-    center: Ray, // 球心坐标
-    radius: f64, // 半径（确保非负）
+    center: Ray,           // 球心坐标
+    motion_time: Interval, // 球心运动的时间区间，区间外保持端点位置（UNIVERSE 表示一直匀速运动）
+    radius: f64,           // 半径（确保非负）
     mat: MaterialPtr,
     bbox: Aabb, // bounding box
 }
@@ -26,24 +29,64 @@ impl Sphere {
         let rvec = Vec3::new(radius, radius, radius);
         Self {
             center: Ray::with_origin_dir(static_center, Vec3::new(0.0, 0.0, 0.0)),
+            motion_time: Interval::new(0.0, 1.0),
             radius: radius.max(0.0), // 确保半径非负
             mat: mat,
             bbox: Aabb::from_points(static_center - rvec, static_center + rvec),
         }
     }
 
+    /// 创建 t = 0 时位于 `center1`、t = 1 时位于 `center2` 的匀速运动球体，区间外按同一速度外推
+    ///
+    /// `bounding_box` 只覆盖 [0, 1] 内的位置，快门在区间外时由 `Camera` 渲染前按快门区间重拟合加速结构
     pub fn new_moving(center1: Point3, center2: Point3, radius: f64, mat: MaterialPtr) -> Self {
         let rvec = Vec3::new(radius, radius, radius);
         let box1 = Aabb::from_points(center1 - rvec, center1 + rvec);
         let box2 = Aabb::from_points(center2 - rvec, center2 + rvec);
         Self {
             center: Ray::with_origin_dir(center1, center2 - center1),
+            motion_time: Interval::UNIVERSE,
             radius: radius.max(0.0),
-            mat: mat,
+            mat,
             bbox: Aabb::from_aabbs(box1, box2),
         }
     }
 
+    /// 创建在 `time1` 到 `time2` 之间从 `center1` 匀速移动到 `center2` 的球体
+    ///
+    /// 时间与相机的 `shutter_open`/`shutter_close` 使用同一时间轴，区间外球心停在端点
+    pub fn new_moving_timed(
+        center1: Point3,
+        time1: f64,
+        center2: Point3,
+        time2: f64,
+        radius: f64,
+        mat: MaterialPtr,
+    ) -> Self {
+        let rvec = Vec3::new(radius, radius, radius);
+        let box1 = Aabb::from_points(center1 - rvec, center1 + rvec);
+        let box2 = Aabb::from_points(center2 - rvec, center2 + rvec);
+
+        // 球心 = origin + t * velocity，并满足 t = time1 时位于 center1
+        let velocity = if time2 > time1 {
+            (center2 - center1) / (time2 - time1)
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        };
+        Self {
+            center: Ray::with_origin_dir(center1 - time1 * velocity, velocity),
+            motion_time: Interval::new(time1, time2.max(time1)),
+            radius: radius.max(0.0),
+            mat,
+            bbox: Aabb::from_aabbs(box1, box2),
+        }
+    }
+
+    /// 给定时刻的球心位置
+    fn center_at(&self, time: f64) -> Point3 {
+        self.center.at(self.motion_time.clamp(time))
+    }
+
     pub fn get_sphere_uv(p: &Point3) -> (f64, f64) {
         let theta = (-p.y()).acos();
         let phi = (-p.z()).atan2(p.x()) + PI;
@@ -70,7 +113,8 @@ impl Sphere {
 /// 实现Hittable trait，使球体可被射线击中
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let current_center = self.center.at(r.time());
+        count_primitive_tests(1);
+        let current_center = self.center_at(r.time());
         let oc = current_center - *r.origin();
         let a = r.direction().length_squared();
         let h = dot(r.direction(), &oc);
@@ -114,6 +158,27 @@ impl Hittable for Sphere {
         self.bbox
     }
 
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("sphere");
+        hasher.write_vec3(self.center.origin());
+        hasher.write_vec3(self.center.direction());
+        hasher.write_f64(self.motion_time.min);
+        hasher.write_f64(self.motion_time.max);
+        hasher.write_f64(self.radius);
+        hasher.write_material(self.mat.as_ref());
+    }
+
+    /// 球心匀速直线运动，区间两端球体的包围盒之并即为所求
+    fn bounding_box_during(&self, time: Interval) -> Aabb {
+        let rvec = Vec3::new(self.radius, self.radius, self.radius);
+        let center1 = self.center_at(time.min);
+        let center2 = self.center_at(time.max);
+        Aabb::from_aabbs(
+            Aabb::from_points(center1 - rvec, center1 + rvec),
+            Aabb::from_points(center2 - rvec, center2 + rvec),
+        )
+    }
+
     fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
         let mut rec = HitRecord::default();
         let ray = Ray::with_origin_dir(*origin, *direction);
@@ -122,7 +187,7 @@ impl Hittable for Sphere {
             return 0.0;
         }
 
-        let center = self.center.at(0.0);
+        let center = self.center_at(0.0);
         let dist_squared = (center - *origin).length_squared();
         let cos_theta_max = (1.0 - self.radius.powi(2) / dist_squared).sqrt();
         let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
@@ -131,7 +196,7 @@ impl Hittable for Sphere {
     }
 
     fn random(&self, origin: &Point3) -> Vec3 {
-        let center = self.center.at(0.0);
+        let center = self.center_at(0.0);
         let direction = center - *origin;
         let distance_squared = direction.length_squared();
 
@@ -0,0 +1,145 @@
+use std::cell::Cell;
+use std::fmt;
+use std::ops::AddAssign;
+use std::time::Duration;
+
+use crate::bvh::BvhStats;
+
+thread_local! {
+    static COUNTERS: Cell<RayCounters> = const { Cell::new(RayCounters::ZERO) };
+}
+
+/// 射线与遍历计数器
+///
+/// 每个线程各自累加一份（见 `count_*` 函数），渲染时每个线程处理完一块像素后用
+/// `take_thread_counters` 取出并合并，不需要原子操作
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RayCounters {
+    pub primary_rays: u64,
+    pub secondary_rays: u64,  // 散射产生的射线
+    pub nodes_visited: u64,   // 测试过包围盒的 BVH 节点数（成束遍历时每束每节点计一次）
+    pub primitive_tests: u64, // 球、三角形、四边形等图元的求交次数
+}
+
+impl RayCounters {
+    pub const ZERO: RayCounters = RayCounters {
+        primary_rays: 0,
+        secondary_rays: 0,
+        nodes_visited: 0,
+        primitive_tests: 0,
+    };
+
+    pub fn total_rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays
+    }
+}
+
+impl AddAssign for RayCounters {
+    fn add_assign(&mut self, other: RayCounters) {
+        self.primary_rays += other.primary_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.nodes_visited += other.nodes_visited;
+        self.primitive_tests += other.primitive_tests;
+    }
+}
+
+fn update(f: impl FnOnce(&mut RayCounters)) {
+    COUNTERS.with(|counters| {
+        let mut value = counters.get();
+        f(&mut value);
+        counters.set(value);
+    });
+}
+
+pub fn count_primary_rays(n: usize) {
+    update(|c| c.primary_rays += n as u64);
+}
+
+pub fn count_secondary_ray() {
+    update(|c| c.secondary_rays += 1);
+}
+
+pub fn count_node_visit() {
+    update(|c| c.nodes_visited += 1);
+}
+
+pub fn count_primitive_tests(n: usize) {
+    update(|c| c.primitive_tests += n as u64);
+}
+
+/// 当前线程累加的计数
+pub fn thread_counters() -> RayCounters {
+    COUNTERS.with(|counters| counters.get())
+}
+
+/// 取出当前线程累加的计数并清零
+pub fn take_thread_counters() -> RayCounters {
+    COUNTERS.with(|counters| counters.replace(RayCounters::ZERO))
+}
+
+/// 一次渲染的统计信息，由 `Camera::render` 打印，`Camera::render_film_with_stats` 返回
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    pub rays: RayCounters,
+    pub render_time: Duration,
+    pub bvh: Option<BvhStats>, // 场景中最大的 BVH（见 `Hittable::bvh_stats`）
+}
+
+impl RenderStats {
+    /// 平均每条路径的射线段数（含主射线）
+    pub fn average_path_length(&self) -> f64 {
+        ratio(self.rays.total_rays(), self.rays.primary_rays)
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.render_time.as_secs_f64();
+        if seconds > 0.0 {
+            self.rays.total_rays() as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// 平均每条射线访问的节点数
+    pub fn nodes_per_ray(&self) -> f64 {
+        ratio(self.rays.nodes_visited, self.rays.total_rays())
+    }
+
+    /// 平均每条射线的图元求交次数
+    pub fn tests_per_ray(&self) -> f64 {
+        ratio(self.rays.primitive_tests, self.rays.total_rays())
+    }
+}
+
+fn ratio(a: u64, b: u64) -> f64 {
+    if b > 0 { a as f64 / b as f64 } else { 0.0 }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "rays: {} primary, {} secondary, average path length {:.2}",
+            self.rays.primary_rays,
+            self.rays.secondary_rays,
+            self.average_path_length()
+        )?;
+        writeln!(
+            f,
+            "traversal: {} nodes visited ({:.1} per ray), {} primitive tests ({:.1} per ray)",
+            self.rays.nodes_visited,
+            self.nodes_per_ray(),
+            self.rays.primitive_tests,
+            self.tests_per_ray()
+        )?;
+        if let Some(bvh) = &self.bvh {
+            writeln!(f, "bvh: {}", bvh)?;
+        }
+        write!(
+            f,
+            "time: {:.2} s, {:.2} Mrays/s",
+            self.render_time.as_secs_f64(),
+            self.rays_per_second() / 1e6
+        )
+    }
+}
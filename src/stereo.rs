@@ -0,0 +1,84 @@
+use crate::camera::Camera;
+use crate::checkpoint::CheckpointError;
+use crate::film::Film;
+use crate::hittable::Hittable;
+use crate::rtweekend::path_with_suffix;
+use std::sync::Arc;
+
+/// 双眼图像的排列方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+    /// 左眼在左，右眼在右
+    SideBySide,
+    /// 左眼在上，右眼在下（360° 全景视频常用）
+    TopBottom,
+}
+
+/// 立体相机：用同一个 `Camera` 分别渲染左右眼并拼成一张图
+///
+/// 透视投影使用平行光轴加水平错位的离轴视锥，使两眼画面在会聚距离处重合；
+/// 等距柱状投影使用全向立体（ODS），眼睛位于直径为瞳距的圆上
+#[derive(Debug, Clone)]
+pub struct StereoCamera {
+    pub camera: Camera,
+    pub interocular: f64, // 瞳距（世界空间单位）
+    pub convergence: f64, // 会聚距离，INFINITY 表示平行视线
+    pub layout: StereoLayout,
+}
+
+impl StereoCamera {
+    pub fn new(camera: Camera) -> Self {
+        let convergence = camera.focus_dist;
+        Self {
+            camera,
+            interocular: 0.064,
+            convergence,
+            layout: StereoLayout::SideBySide,
+        }
+    }
+
+    /// 渲染左右眼并以 PPM 格式输出拼接后的图像
+    pub fn render(
+        &self,
+        world: Arc<dyn Hittable + Send + Sync>,
+        lights: Arc<dyn Hittable + Send + Sync>,
+    ) {
+        match self.render_film(world, lights) {
+            Ok(film) => film.write_stdout(),
+            Err(e) => eprintln!("\nERROR: {}", e),
+        }
+    }
+
+    /// 渲染左右眼并返回拼接后的图像
+    pub fn render_film(
+        &self,
+        world: Arc<dyn Hittable + Send + Sync>,
+        lights: Arc<dyn Hittable + Send + Sync>,
+    ) -> Result<Film, CheckpointError> {
+        eprintln!("渲染左眼...");
+        let left = self
+            .eye_camera(-0.5, "left")
+            .render_film(Arc::clone(&world), Arc::clone(&lights))?;
+        eprintln!("\n渲染右眼...");
+        let right = self.eye_camera(0.5, "right").render_film(world, lights)?;
+
+        Ok(match self.layout {
+            StereoLayout::SideBySide => Film::side_by_side(&left, &right),
+            StereoLayout::TopBottom => Film::top_bottom(&left, &right),
+        })
+    }
+
+    /// 单只眼睛的相机，`side` 为 -0.5（左）或 0.5（右）
+    fn eye_camera(&self, side: f64, name: &str) -> Camera {
+        let mut camera = self.camera.clone();
+        camera.eye_offset = side * self.interocular;
+        camera.convergence_dist = self.convergence;
+
+        // 两只眼睛各自保存断点
+        if let Some(path) = &camera.checkpoint_path {
+            camera.checkpoint_path = Some(path_with_suffix(path, name));
+        }
+
+        camera
+    }
+}
@@ -100,13 +100,17 @@
 
 use crate::{
     aabb::Aabb,
+    checkpoint::SceneHasher,
     hittable::HitRecord,
     hittable::Hittable,
     interval::Interval,
     material::MaterialPtr,
+    packet::{LANES, PacketHits, RayPacket, cross_lanes, dot_lanes, group_mask, splat},
     ray::Ray,
+    stats::count_primitive_tests,
     vec3::{Point3, Vec3, cross, dot, unit_vector},
 };
+use glam::DVec4;
 
 #[derive(Debug)]
 pub struct Triangle {
@@ -114,6 +118,8 @@ pub struct Triangle {
     v1: Point3,
     v2: Point3,
     normal: Vec3,
+    vertex_normals: Option<[Vec3; 3]>, // 有顶点法向时插值得到着色法向
+    vertex_uvs: Option<[[f64; 2]; 3]>, // 有顶点纹理坐标时插值得到 (u, v)，否则为重心坐标
     material: MaterialPtr,
     bbox: Aabb,
 }
@@ -143,62 +149,205 @@ impl Triangle {
             v1,
             v2,
             normal,
+            vertex_normals: None,
+            vertex_uvs: None,
             material,
             bbox,
         }
     }
+
+    /// 设置顶点法向，着色法向由三个顶点法向按重心坐标插值，表面看起来是光滑的
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.vertex_normals = Some(normals);
+        self
+    }
+
+    /// 设置顶点纹理坐标，命中记录中的 (u, v) 由它们按重心坐标插值
+    pub fn with_uvs(mut self, uvs: [[f64; 2]; 3]) -> Self {
+        self.vertex_uvs = Some(uvs);
+        self
+    }
+
+    fn record(&self, r: &Ray, t: f64, u: f64, v: f64, rec: &mut HitRecord) {
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.set_face_normal(r, self.normal);
+        if let Some(normal) = self
+            .vertex_normals
+            .as_ref()
+            .and_then(|[n0, n1, n2]| interpolate_normal([n0, n1, n2], u, v))
+        {
+            rec.set_shading_normal(normal);
+        }
+        rec.mat = Some(self.material.clone());
+        (rec.u, rec.v) = match &self.vertex_uvs {
+            Some([uv0, uv1, uv2]) => interpolate_uv([uv0, uv1, uv2], u, v),
+            None => (u, v),
+        };
+    }
 }
 
 impl Hittable for Triangle {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        // Möller–Trumbore 算法
-        let edge1 = self.v1 - self.v0;
-        let edge2 = self.v2 - self.v0;
-        let h = cross(r.direction(), &edge2);
-        let a = dot(&edge1, &h);
-
-        // 如果射线与三角形平面平行
-        if a.abs() < 1e-8 {
+        count_primitive_tests(1);
+        let Some((t, u, v)) = intersect(&self.v0, &self.v1, &self.v2, r, ray_t) else {
             return false;
-        }
+        };
+
+        // 填充命中记录
+        self.record(r, t, u, v, rec);
+        true
+    }
 
-        let f = 1.0 / a;
-        let s = *r.origin() - self.v0;
-        let u = f * dot(&s, &h);
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
 
-        // 检查 u 是否在三角形范围内
-        if u < 0.0 || u > 1.0 {
-            return false;
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("triangle");
+        for v in [&self.v0, &self.v1, &self.v2] {
+            hasher.write_vec3(v);
         }
+        if let Some(normals) = &self.vertex_normals {
+            normals.iter().for_each(|n| hasher.write_vec3(n));
+        }
+        if let Some(uvs) = &self.vertex_uvs {
+            uvs.iter().flatten().for_each(|&x| hasher.write_f64(x));
+        }
+        hasher.write_material(self.material.as_ref());
+    }
+
+    fn hit_packet(&self, packet: &RayPacket, active: u64, hits: &mut PacketHits) {
+        count_primitive_tests(active.count_ones() as usize);
+        hit_lanes(
+            [&self.v0, &self.v1, &self.v2],
+            packet,
+            active,
+            hits,
+            |r, t, u, v, rec| self.record(r, t, u, v, rec),
+        );
+    }
+}
 
-        let q = cross(&s, &edge1);
-        let v = f * dot(r.direction(), &q);
+/// 按重心坐标 (u, v) 插值三个顶点的纹理坐标
+pub fn interpolate_uv([uv0, uv1, uv2]: [&[f64; 2]; 3], u: f64, v: f64) -> (f64, f64) {
+    let w = 1.0 - u - v;
+    (
+        w * uv0[0] + u * uv1[0] + v * uv2[0],
+        w * uv0[1] + u * uv1[1] + v * uv2[1],
+    )
+}
 
-        // 检查 v 是否在三角形范围内
-        if v < 0.0 || u + v > 1.0 {
-            return false;
-        }
+/// 按重心坐标 (u, v) 插值三个顶点法向，结果退化为零向量时返回 `None`
+pub fn interpolate_normal([n0, n1, n2]: [&Vec3; 3], u: f64, v: f64) -> Option<Vec3> {
+    let n = (1.0 - u - v) * *n0 + u * *n1 + v * *n2;
+    let length_squared = n.length_squared();
+    if length_squared > 1e-16 {
+        Some(n / length_squared.sqrt())
+    } else {
+        None
+    }
+}
 
-        // 计算射线参数 t
-        let t = f * dot(&edge2, &q);
+/// Möller–Trumbore 求交，返回 (t, u, v)，其中 u、v 为 v1、v2 的重心坐标
+pub fn intersect(
+    v0: &Point3,
+    v1: &Point3,
+    v2: &Point3,
+    r: &Ray,
+    ray_t: Interval,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = *v1 - *v0;
+    let edge2 = *v2 - *v0;
+    let h = cross(r.direction(), &edge2);
+    let a = dot(&edge1, &h);
+
+    // 如果射线与三角形平面平行
+    if a.abs() < 1e-8 {
+        return None;
+    }
 
-        // 检查 t 是否在有效范围内
-        if !ray_t.contains(t) {
-            return false;
-        }
+    let f = 1.0 / a;
+    let s = *r.origin() - *v0;
+    let u = f * dot(&s, &h);
 
-        // 填充命中记录
-        rec.t = t;
-        rec.p = r.at(t);
-        rec.set_face_normal(r, self.normal);
-        rec.mat = Some(self.material.clone());
-        rec.u = u;
-        rec.v = v;
+    // 检查 u 是否在三角形范围内
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
 
-        true
+    let q = cross(&s, &edge1);
+    let v = f * dot(r.direction(), &q);
+
+    // 检查 v 是否在三角形范围内
+    if v < 0.0 || u + v > 1.0 {
+        return None;
     }
 
-    fn bounding_box(&self) -> Aabb {
-        self.bbox
+    // 计算射线参数 t，检查是否在有效范围内
+    let t = f * dot(&edge2, &q);
+    if !ray_t.contains(t) {
+        return None;
+    }
+
+    Some((t, u, v))
+}
+
+/// 四条射线一组做 Möller–Trumbore 测试，各通道的运算与 `intersect` 完全相同
+///
+/// 第 k 条射线击中更近的交点时以 (射线, t, u, v, hits.recs[k]) 调用 `record` 填写命中记录
+pub fn hit_lanes(
+    [v0, v1, v2]: [&Point3; 3],
+    packet: &RayPacket,
+    active: u64,
+    hits: &mut PacketHits,
+    mut record: impl FnMut(&Ray, f64, f64, f64, &mut HitRecord),
+) {
+    let edge1 = splat(&(*v1 - *v0));
+    let edge2 = splat(&(*v2 - *v0));
+    let v0 = splat(v0);
+
+    for (g, lanes) in packet.lanes().iter().enumerate() {
+        let group = group_mask(active, g);
+        if group == 0 {
+            continue;
+        }
+
+        let h = cross_lanes(&lanes.direction, &edge2);
+        let a = dot_lanes(&edge1, &h);
+        let f = DVec4::ONE / a;
+        let s = [
+            lanes.origin[0] - v0[0],
+            lanes.origin[1] - v0[1],
+            lanes.origin[2] - v0[2],
+        ];
+        let u = f * dot_lanes(&s, &h);
+        let q = cross_lanes(&s, &edge1);
+        let v = f * dot_lanes(&lanes.direction, &q);
+        let t = f * dot_lanes(&edge2, &q);
+
+        let inside = a.abs().cmpge(DVec4::splat(1e-8))
+            & u.cmpge(DVec4::ZERO)
+            & u.cmple(DVec4::ONE)
+            & v.cmpge(DVec4::ZERO)
+            & (u + v).cmple(DVec4::ONE)
+            & t.cmpge(DVec4::splat(hits.t_min))
+            & t.cmple(hits.t_max_lanes(g));
+        let mut mask = inside.bitmask() as u64 & group;
+
+        while mask != 0 {
+            let lane = mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+
+            let k = g * LANES + lane;
+            record(
+                &packet.rays()[k],
+                t[lane],
+                u[lane],
+                v[lane],
+                &mut hits.recs[k],
+            );
+            hits.record(k);
+        }
     }
 }
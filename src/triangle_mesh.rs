@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+
+use crate::aabb::Aabb;
+use crate::bvh::{BvhOptions, BvhStats};
+use crate::checkpoint::SceneHasher;
+use crate::flat_bvh::{FlatNode, build_nodes, traverse, traverse_packet};
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::MaterialPtr;
+use crate::packet::{PacketHits, RayPacket, hit_each};
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::stats::count_primitive_tests;
+use crate::triangle::{hit_lanes, interpolate_normal, interpolate_uv, intersect};
+use crate::vec3::{Point3, Vec3, cross, dot, unit_vector};
+
+/// 坐标轴
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    fn index(self) -> usize {
+        match self {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        }
+    }
+}
+
+/// 为没有纹理坐标的网格生成 (u, v) 的投影方式，坐标按顶点的包围盒归一化到 [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UvProjection {
+    /// 沿 `axis` 轴投影到与之垂直的平面，u、v 依次取其后的两个轴（x→y→z→x）
+    Planar { axis: Axis },
+    /// 每个面沿其法向的主轴做平面投影，相当于从三个方向分别投影；
+    /// 相邻面投影方向不同时共享的顶点会被拆开
+    Box,
+    /// 以包围盒中心为球心的经纬度映射，与 `Sphere` 的纹理坐标相同
+    ///
+    /// 跨越经度接缝的面在接缝一侧的 u 加 1（纹理按 [0, 1] 截断，接缝处只差一个面宽的边缘），
+    /// 位于两极的顶点取所在面其余顶点 u 的平均；这些顶点与相邻面不再共享
+    Spherical,
+}
+
+/// 共享顶点的三角形网格
+///
+/// 顶点位置、法向和纹理坐标各只存一份，三角形只保存三个顶点下标。网格自带一棵以三角形为图元的
+/// 线性化 BVH，遍历到叶节点时直接按下标求交，不需要为每个面创建一个 `Triangle` 对象
+#[derive(Debug)]
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,     // 顶点法向，为空表示没有
+    uvs: Vec<[f64; 2]>,     // 顶点纹理坐标，为空表示没有
+    indices: Vec<[u32; 3]>, // 按 BVH 叶节点的顺序排列
+    nodes: Vec<FlatNode>,
+    stats: BvhStats,
+    materials: Vec<MaterialPtr>,
+    face_materials: Vec<u32>, // 各三角形在 materials 中的下标，与 indices 顺序相同；为空表示都用第一个
+}
+
+impl TriangleMesh {
+    /// 只有顶点位置的网格，使用默认的构建参数
+    pub fn new(positions: Vec<Point3>, indices: Vec<[u32; 3]>, material: MaterialPtr) -> Self {
+        Self::with_attributes(
+            positions,
+            Vec::new(),
+            Vec::new(),
+            indices,
+            material,
+            Self::default_options(),
+        )
+    }
+
+    /// `normals` 和 `uvs` 为空或与 `positions` 一一对应
+    pub fn with_attributes(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<[f64; 2]>,
+        indices: Vec<[u32; 3]>,
+        material: MaterialPtr,
+        options: BvhOptions,
+    ) -> Self {
+        Self::with_materials(
+            positions,
+            normals,
+            uvs,
+            indices,
+            vec![material],
+            Vec::new(),
+            options,
+        )
+    }
+
+    /// 逐面指定材质：`face_materials` 为空或与 `indices` 一一对应，元素是 `materials` 中的下标
+    pub fn with_materials(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<[f64; 2]>,
+        indices: Vec<[u32; 3]>,
+        materials: Vec<MaterialPtr>,
+        face_materials: Vec<u32>,
+        options: BvhOptions,
+    ) -> Self {
+        assert!(!materials.is_empty(), "a mesh needs at least one material");
+        assert!(
+            face_materials.is_empty()
+                || (face_materials.len() == indices.len()
+                    && face_materials
+                        .iter()
+                        .all(|&m| (m as usize) < materials.len())),
+            "face materials must match the triangle count and index into materials"
+        );
+        assert!(
+            normals.is_empty() || normals.len() == positions.len(),
+            "vertex normals must match the vertex count"
+        );
+        assert!(
+            uvs.is_empty() || uvs.len() == positions.len(),
+            "texture coordinates must match the vertex count"
+        );
+        assert!(
+            indices
+                .iter()
+                .flatten()
+                .all(|&i| (i as usize) < positions.len()),
+            "triangle index out of range"
+        );
+
+        let boxes: Vec<Aabb> = indices
+            .iter()
+            .map(|&[a, b, c]| {
+                let (a, b, c) = (
+                    positions[a as usize],
+                    positions[b as usize],
+                    positions[c as usize],
+                );
+                Aabb::from_points(a.min(b).min(c), a.max(b).max(c))
+            })
+            .collect();
+        let (nodes, order, stats) = build_nodes(&boxes, &options);
+        let indices = order.iter().map(|&i| indices[i]).collect();
+        let face_materials = if face_materials.is_empty() {
+            face_materials
+        } else {
+            order.iter().map(|&i| face_materials[i]).collect()
+        };
+
+        Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            nodes,
+            stats,
+            materials,
+            face_materials,
+        }
+    }
+
+    /// 用已构建好的 BVH 节点组装（例如从 `MeshCache` 读取），不再检查数据也不再划分
+    ///
+    /// `bvh` 为节点与构建时的统计信息，`indices` 与 `face_materials` 须已按叶节点的顺序排列，
+    /// 其余要求与 `with_materials` 相同
+    pub(crate) fn from_parts(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<[f64; 2]>,
+        indices: Vec<[u32; 3]>,
+        materials: Vec<MaterialPtr>,
+        face_materials: Vec<u32>,
+        (nodes, stats): (Vec<FlatNode>, BvhStats),
+    ) -> Self {
+        Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            nodes,
+            stats,
+            materials,
+            face_materials,
+        }
+    }
+
+    /// 分桶 SAH，叶节点最多 4 个三角形：节点数约为三角形数的一半，遍历代价与每叶一个三角形相近
+    pub fn default_options() -> BvhOptions {
+        BvhOptions::sah(16, 4)
+    }
+
+    pub fn positions(&self) -> &[Point3] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> &[Vec3] {
+        &self.normals
+    }
+
+    pub fn uvs(&self) -> &[[f64; 2]] {
+        &self.uvs
+    }
+
+    /// 各三角形的顶点下标，按 BVH 叶节点的顺序排列
+    pub fn indices(&self) -> &[[u32; 3]] {
+        &self.indices
+    }
+
+    /// 各三角形的材质下标，与 `indices` 顺序相同；为空表示都用第一个材质
+    pub fn face_materials(&self) -> &[u32] {
+        &self.face_materials
+    }
+
+    pub fn nodes(&self) -> &[FlatNode] {
+        &self.nodes
+    }
+
+    /// 按 `projection` 生成所有顶点的纹理坐标，替换已有的纹理坐标
+    ///
+    /// 三角形的顺序不变，不需要重建 BVH
+    pub fn generate_uvs(&mut self, projection: UvProjection) {
+        let (min, max) = self.positions.iter().fold(
+            (
+                Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+                Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            ),
+            |(min, max), p| (min.min(*p), max.max(*p)),
+        );
+        // 把 p 在 axis 轴上的坐标归一化到 [0, 1]，包围盒在该轴上没有厚度时取 0
+        let normalized = |p: &Point3, axis: usize| {
+            let extent = max[axis] - min[axis];
+            if extent > 0.0 {
+                (p[axis] - min[axis]) / extent
+            } else {
+                0.0
+            }
+        };
+        let planar = |p: &Point3, axis: usize| {
+            [normalized(p, (axis + 1) % 3), normalized(p, (axis + 2) % 3)]
+        };
+
+        match projection {
+            UvProjection::Planar { axis } => {
+                self.uvs = self
+                    .positions
+                    .iter()
+                    .map(|p| planar(p, axis.index()))
+                    .collect();
+            }
+            UvProjection::Box => self.split_by_uv(|[a, b, c]| {
+                let n = cross(&(*b - *a), &(*c - *a));
+                let axis = (0..3)
+                    .max_by(|&i, &j| n[i].abs().total_cmp(&n[j].abs()))
+                    .unwrap();
+                [a, b, c].map(|p| planar(p, axis))
+            }),
+            UvProjection::Spherical => {
+                let center = 0.5 * (min + max);
+                // (纹理坐标, 是否在极轴上)，极轴上（包括球心）经度没有定义
+                let spherical = |p: &Point3| {
+                    let d = *p - center;
+                    if d.length_squared() <= 0.0 {
+                        return ([0.0, 0.0], true);
+                    }
+                    let (u, v) = Sphere::get_sphere_uv(&unit_vector(d));
+                    ([u, v], d.x().hypot(d.z()) <= 1e-9 * d.length())
+                };
+
+                self.split_by_uv(|face| {
+                    let mut uvs = face.map(spherical);
+                    let (lo, hi) = uvs
+                        .iter()
+                        .filter(|(_, pole)| !pole)
+                        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), (uv, _)| {
+                            (lo.min(uv[0]), hi.max(uv[0]))
+                        });
+                    if hi - lo > 0.5 {
+                        for (uv, pole) in &mut uvs {
+                            if !*pole && uv[0] < 0.5 {
+                                uv[0] += 1.0;
+                            }
+                        }
+                    }
+
+                    let (sum, count) = uvs
+                        .iter()
+                        .filter(|(_, pole)| !pole)
+                        .fold((0.0, 0), |(sum, count), (uv, _)| (sum + uv[0], count + 1));
+                    if count > 0 {
+                        for (uv, pole) in &mut uvs {
+                            if *pole {
+                                uv[0] = sum / count as f64;
+                            }
+                        }
+                    }
+                    uvs.map(|(uv, _)| uv)
+                });
+            }
+        }
+    }
+
+    /// 按 `face_uvs` 给出的每个面三个顶点的纹理坐标重建顶点缓冲
+    ///
+    /// 每个 (顶点, 纹理坐标) 对应一个新顶点：同一顶点在相邻面上坐标相同时仍然共享，不同时被拆开
+    fn split_by_uv(&mut self, mut face_uvs: impl FnMut([&Point3; 3]) -> [[f64; 2]; 3]) {
+        let mut split: HashMap<(u32, [u64; 2]), u32> = HashMap::new();
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+
+        for face in &mut self.indices {
+            let face_uv = face_uvs(face.map(|i| &self.positions[i as usize]));
+            for (i, uv) in face.iter_mut().zip(face_uv) {
+                let key = (*i, uv.map(f64::to_bits));
+                *i = *split.entry(key).or_insert_with(|| {
+                    positions.push(self.positions[*i as usize]);
+                    if !self.normals.is_empty() {
+                        normals.push(self.normals[*i as usize]);
+                    }
+                    uvs.push(uv);
+                    (positions.len() - 1) as u32
+                });
+            }
+        }
+
+        self.positions = positions;
+        self.normals = normals;
+        self.uvs = uvs;
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
+
+    /// 顶点缓冲、下标和 BVH 节点占用的字节数
+    pub fn memory_bytes(&self) -> usize {
+        size_of::<Self>()
+            + self.positions.capacity() * size_of::<Point3>()
+            + self.normals.capacity() * size_of::<Vec3>()
+            + self.uvs.capacity() * size_of::<[f64; 2]>()
+            + self.indices.capacity() * size_of::<[u32; 3]>()
+            + self.face_materials.capacity() * size_of::<u32>()
+            + self.nodes.capacity() * size_of::<FlatNode>()
+    }
+
+    fn vertices(&self, k: usize) -> [&Point3; 3] {
+        self.indices[k].map(|i| &self.positions[i as usize])
+    }
+
+    /// 第 k 个三角形在 (t, u, v) 处的命中记录，有顶点法向时着色法向取插值结果
+    fn record(&self, k: usize, r: &Ray, t: f64, u: f64, v: f64, rec: &mut HitRecord) {
+        let [a, b, c] = self.vertices(k);
+        let normal = unit_vector(cross(&(*b - *a), &(*c - *a)));
+
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.set_face_normal(r, normal);
+        if !self.normals.is_empty() {
+            let normals = self.indices[k].map(|i| &self.normals[i as usize]);
+            if let Some(shading_normal) = interpolate_normal(normals, u, v) {
+                rec.set_shading_normal(shading_normal);
+            }
+        }
+        let material = self.face_materials.get(k).map_or(0, |&m| m as usize);
+        rec.mat = Some(self.materials[material].clone());
+        (rec.u, rec.v) = if self.uvs.is_empty() {
+            (u, v)
+        } else {
+            interpolate_uv(self.indices[k].map(|i| &self.uvs[i as usize]), u, v)
+        };
+    }
+}
+
+/// 按夹角加权平均相邻面的法向，得到各顶点的法向
+///
+/// 每个面对顶点的贡献与该面在顶点处的内角成正比，与面的细分方式无关；
+/// 没有被任何非退化三角形引用的顶点法向为零向量
+pub fn vertex_normals(positions: &[Point3], indices: &[[u32; 3]]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::default(); positions.len()];
+
+    for face in indices {
+        let [a, b, c] = face.map(|i| positions[i as usize]);
+        let face_normal = cross(&(b - a), &(c - a));
+        if face_normal.length_squared() <= 0.0 {
+            continue;
+        }
+        let face_normal = unit_vector(face_normal);
+
+        let corners = [(a, b, c), (b, c, a), (c, a, b)];
+        for (&i, (p, next, prev)) in face.iter().zip(corners) {
+            let cos_angle = dot(&unit_vector(next - p), &unit_vector(prev - p));
+            normals[i as usize] += cos_angle.clamp(-1.0, 1.0).acos() * face_normal;
+        }
+    }
+
+    for n in &mut normals {
+        if n.length_squared() > 0.0 {
+            *n = unit_vector(*n);
+        }
+    }
+    normals
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        traverse(&self.nodes, r, ray_t, rec, |k, ray_t, rec| {
+            count_primitive_tests(1);
+            let [a, b, c] = self.vertices(k);
+            match intersect(a, b, c, r, ray_t) {
+                Some((t, u, v)) => {
+                    self.record(k, r, t, u, v, rec);
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |node| node.bbox)
+    }
+
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("triangle_mesh");
+        for buffer in [&self.positions, &self.normals] {
+            hasher.write_u64(buffer.len() as u64);
+            buffer.iter().for_each(|v| hasher.write_vec3(v));
+        }
+        hasher.write_u64(self.uvs.len() as u64);
+        self.uvs.iter().flatten().for_each(|&x| hasher.write_f64(x));
+        hasher.write_u64(self.indices.len() as u64);
+        self.indices
+            .iter()
+            .flatten()
+            .for_each(|&i| hasher.write_u64(i as u64));
+        for material in &self.materials {
+            hasher.write_material(material.as_ref());
+        }
+        self.face_materials
+            .iter()
+            .for_each(|&m| hasher.write_u64(m as u64));
+    }
+
+    fn hit_packet(&self, packet: &RayPacket, active: u64, hits: &mut PacketHits) {
+        if !packet.is_coherent() {
+            hit_each(self, packet, active, hits);
+            return;
+        }
+
+        traverse_packet(&self.nodes, packet, active, hits, |k, mask, hits| {
+            count_primitive_tests(mask.count_ones() as usize);
+            hit_lanes(self.vertices(k), packet, mask, hits, |r, t, u, v, rec| {
+                self.record(k, r, t, u, v, rec)
+            });
+        });
+    }
+
+    fn bvh_stats(&self) -> Option<BvhStats> {
+        Some(self.stats)
+    }
+}
@@ -0,0 +1,457 @@
+use glam::Vec4;
+use rayon::prelude::*;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::aabb::Aabb;
+use crate::bvh::{BvhOptions, BvhStats, INTERSECT_COST, TRAVERSAL_COST};
+use crate::checkpoint::SceneHasher;
+use crate::flat_bvh::{FlatNode, TraversalStack, build_nodes};
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::stats::count_node_visit;
+
+/// 四叉 BVH 的宽度
+const WIDTH: usize = 4;
+
+/// 遍历栈放在栈上的容量：每层最多压入 WIDTH - 1 个兄弟节点，更深的树溢出到堆上
+const STACK_SIZE: usize = 128;
+
+/// 空槽位的标记
+const EMPTY_SLOT: u32 = u32::MAX;
+
+/// 补偿 f32 计算误差，使 slab 测试只会多报、不会漏报
+const T_FAR_SCALE: f32 = 1.0 + 2.0 * 3.0 * f32::EPSILON;
+
+/// 包围盒外扩的相对量
+const BOX_PADDING: f64 = 1e-5;
+
+/// 四叉 BVH 的节点，四个子节点的包围盒按分量存放（SoA），一次 SIMD 运算测试四个盒子
+///
+/// `counts[k] == 0` 时 `children[k]` 为内部子节点下标，否则为叶节点第一个图元的下标；
+/// 空槽位的 `children[k]` 为 `EMPTY_SLOT`，包围盒为空（min > max），永远不会被击中
+#[derive(Debug, Clone, Copy)]
+pub struct WideNode {
+    min_x: Vec4,
+    min_y: Vec4,
+    min_z: Vec4,
+    max_x: Vec4,
+    max_y: Vec4,
+    max_z: Vec4,
+    children: [u32; WIDTH],
+    counts: [u32; WIDTH],
+}
+
+/// 由二叉 BVH 折叠得到的四叉 BVH
+///
+/// 包围盒以 f32 存储并向外取整，测试结果偏保守，命中判定仍由图元以 f64 完成。
+/// 与 `FlatBvh` 一样可用 `refit` 逐帧更新包围盒
+#[derive(Debug, Clone)]
+pub struct WideBvh {
+    nodes: Vec<WideNode>,
+    objects: Vec<Arc<dyn Hittable + Send + Sync>>,
+    bbox: Aabb,
+    stats: BvhStats,
+    options: BvhOptions,
+    cost: f64, // 最近一次重拟合后按四叉树计算的 SAH 代价
+    /// 衡量重拟合后树质量的基准，含义与 `FlatBvh` 相同，但按四叉树计算
+    build_cost: Option<f64>,
+}
+
+impl WideBvh {
+    pub fn new(list: &HittableList, options: BvhOptions) -> Self {
+        Self::from_objects(list.objects.clone(), options)
+    }
+
+    pub fn from_objects(
+        objects: Vec<Arc<dyn Hittable + Send + Sync>>,
+        options: BvhOptions,
+    ) -> Self {
+        let start = Instant::now();
+        let boxes: Vec<Aabb> = objects.iter().map(|o| o.bounding_box()).collect();
+        let (flat, order, stats) = build_nodes(&boxes, &options);
+        let objects = order.iter().map(|&i| objects[i].clone()).collect();
+
+        let mut bvh = Self::from_nodes(&flat, objects, stats, options);
+        bvh.stats.build_time = start.elapsed();
+        bvh
+    }
+
+    /// 折叠已构建好的二叉节点，`objects` 须已按叶节点顺序排列，`options` 用于重拟合后的重建
+    pub fn from_nodes(
+        flat: &[FlatNode],
+        objects: Vec<Arc<dyn Hittable + Send + Sync>>,
+        stats: BvhStats,
+        options: BvhOptions,
+    ) -> Self {
+        let mut nodes = Vec::with_capacity(flat.len() / 2 + 1);
+        let bbox = flat.first().map_or(Aabb::EMPTY, |node| node.bbox);
+        if !flat.is_empty() {
+            collapse(flat, 0, &mut nodes);
+        }
+
+        Self {
+            nodes,
+            objects,
+            bbox,
+            stats,
+            options,
+            cost: stats.sah_cost,
+            build_cost: None,
+        }
+    }
+
+    /// 二叉树构建时的统计信息，构建时间包括折叠为四叉树；重拟合不更新，树的质量见 `degradation`
+    pub fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
+
+    /// 树的退化程度：当前四叉树 SAH 代价与基准之比，刚构建完或刚记录基准时为 1
+    pub fn degradation(&self) -> f64 {
+        match self.build_cost {
+            Some(cost) if cost > 0.0 => self.cost / cost,
+            _ => 1.0,
+        }
+    }
+
+    /// 按图元在 `time` 内的包围盒自底向上更新各节点，树的拓扑不变
+    ///
+    /// 规则与 `FlatBvh::refit` 相同：先更新图元，构建后的第一次重拟合只记录基准代价，
+    /// 之后退化程度超过 `BvhOptions::rebuild_threshold` 时完全重建。返回是否进行了重建
+    pub fn refit(&mut self, time: Interval) -> bool {
+        self.objects.par_iter_mut().for_each(|object| {
+            if let Some(refitted) = object.refitted(time) {
+                *object = refitted;
+            }
+        });
+        let boxes = self.object_boxes(time);
+        self.cost = self.refit_nodes(&boxes);
+
+        if self.build_cost.is_none() {
+            self.build_cost = Some(self.cost);
+            return false;
+        }
+        if self.degradation() > self.options.rebuild_threshold {
+            self.rebuild_with(&boxes);
+            return true;
+        }
+        false
+    }
+
+    /// 按图元在 `time` 内的包围盒完全重建
+    pub fn rebuild(&mut self, time: Interval) {
+        let boxes = self.object_boxes(time);
+        self.rebuild_with(&boxes);
+    }
+
+    /// 以 `boxes[i]` 作为第 i 个图元的包围盒重建
+    fn rebuild_with(&mut self, boxes: &[Aabb]) {
+        let start = Instant::now();
+        let (flat, order, stats) = build_nodes(boxes, &self.options);
+        let objects = order.iter().map(|&i| self.objects[i].clone()).collect();
+        let boxes: Vec<Aabb> = order.iter().map(|&i| boxes[i]).collect();
+
+        *self = Self::from_nodes(&flat, objects, stats, self.options);
+        self.stats.build_time = start.elapsed();
+        self.cost = self.refit_nodes(&boxes);
+        self.build_cost = Some(self.cost);
+    }
+
+    /// 以 `boxes[k]` 作为第 k 个图元的包围盒更新各节点，返回四叉树的 SAH 代价
+    ///
+    /// 子节点的下标总是大于父节点，倒序遍历即为自底向上
+    fn refit_nodes(&mut self, boxes: &[Aabb]) -> f64 {
+        let mut node_boxes = vec![Aabb::EMPTY; self.nodes.len()];
+        let mut costs = vec![0.0; self.nodes.len()];
+        for i in (0..self.nodes.len()).rev() {
+            let node = &mut self.nodes[i];
+            let mut child_boxes = [Aabb::EMPTY; WIDTH];
+            let mut child_costs = [0.0; WIDTH];
+            for (k, (&child, &count)) in node.children.iter().zip(&node.counts).enumerate() {
+                if child == EMPTY_SLOT {
+                    continue;
+                }
+                if count > 0 {
+                    let start = child as usize;
+                    child_boxes[k] = boxes[start..start + count as usize]
+                        .iter()
+                        .fold(Aabb::EMPTY, |acc, &b| Aabb::from_aabbs(acc, b));
+                    child_costs[k] = INTERSECT_COST * count as f64;
+                } else {
+                    child_boxes[k] = node_boxes[child as usize];
+                    child_costs[k] = costs[child as usize];
+                }
+            }
+
+            let bbox = child_boxes
+                .iter()
+                .fold(Aabb::EMPTY, |acc, &b| Aabb::from_aabbs(acc, b));
+            let area = bbox.surface_area();
+            let mut cost = TRAVERSAL_COST;
+            for (k, (child_box, child_cost)) in child_boxes.iter().zip(child_costs).enumerate() {
+                if node.children[k] == EMPTY_SLOT {
+                    continue;
+                }
+                node.set_bbox(k, child_box);
+                cost += if area > 0.0 {
+                    child_box.surface_area() / area * child_cost
+                } else {
+                    child_cost
+                };
+            }
+            node_boxes[i] = bbox;
+            costs[i] = cost;
+        }
+
+        self.bbox = node_boxes.first().copied().unwrap_or(Aabb::EMPTY);
+        costs.first().copied().unwrap_or(0.0)
+    }
+
+    fn object_boxes(&self, time: Interval) -> Vec<Aabb> {
+        self.objects
+            .par_iter()
+            .map(|object| object.bounding_box_during(time))
+            .collect()
+    }
+
+    /// 四叉节点的数量
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn hit_leaf(
+        &self,
+        start: u32,
+        count: u32,
+        r: &Ray,
+        ray_t: Interval,
+        closest_so_far: &mut f64,
+        rec: &mut HitRecord,
+    ) -> bool {
+        let start = start as usize;
+        let mut hit_anything = false;
+        for object in &self.objects[start..start + count as usize] {
+            if object.hit(r, Interval::new(ray_t.min, *closest_so_far), rec) {
+                hit_anything = true;
+                *closest_so_far = rec.t;
+            }
+        }
+        hit_anything
+    }
+}
+
+/// 把以 `flat[root]` 为根的二叉子树折叠成四叉节点，返回新节点下标
+///
+/// 每次展开子节点中表面积最大的内部节点，直到凑满四个或全是叶节点
+fn collapse(flat: &[FlatNode], root: usize, nodes: &mut Vec<WideNode>) -> u32 {
+    let node_index = nodes.len();
+    nodes.push(WideNode::empty());
+
+    let root_node = &flat[root];
+    let mut slots: Vec<usize> = if root_node.is_leaf() {
+        vec![root]
+    } else {
+        vec![root + 1, root_node.offset as usize]
+    };
+
+    while slots.len() < WIDTH {
+        let candidate = slots
+            .iter()
+            .enumerate()
+            .filter(|&(_, &i)| !flat[i].is_leaf())
+            .max_by(|&(_, &a), &(_, &b)| {
+                flat[a]
+                    .bbox
+                    .surface_area()
+                    .partial_cmp(&flat[b].bbox.surface_area())
+                    .unwrap()
+            })
+            .map(|(k, _)| k);
+
+        let Some(k) = candidate else {
+            break;
+        };
+        let i = slots.swap_remove(k);
+        slots.push(i + 1);
+        slots.push(flat[i].offset as usize);
+    }
+
+    let mut node = WideNode::empty();
+    for (k, &i) in slots.iter().enumerate() {
+        let child = &flat[i];
+        node.set_bbox(k, &child.bbox);
+        if child.is_leaf() {
+            node.children[k] = child.offset;
+            node.counts[k] = child.count;
+        } else {
+            node.children[k] = collapse(flat, i, nodes);
+        }
+    }
+    nodes[node_index] = node;
+
+    node_index as u32
+}
+
+impl WideNode {
+    fn empty() -> Self {
+        Self {
+            min_x: Vec4::INFINITY,
+            min_y: Vec4::INFINITY,
+            min_z: Vec4::INFINITY,
+            max_x: Vec4::NEG_INFINITY,
+            max_y: Vec4::NEG_INFINITY,
+            max_z: Vec4::NEG_INFINITY,
+            children: [EMPTY_SLOT; WIDTH],
+            counts: [0; WIDTH],
+        }
+    }
+
+    /// 写入第 k 个子节点的包围盒，并按坐标大小略微外扩以吸收射线起点转为 f32 的误差
+    fn set_bbox(&mut self, k: usize, bbox: &Aabb) {
+        let pad = |v: f64| BOX_PADDING * (1.0 + v.abs());
+        self.min_x[k] = round_down(bbox.x.min - pad(bbox.x.min));
+        self.min_y[k] = round_down(bbox.y.min - pad(bbox.y.min));
+        self.min_z[k] = round_down(bbox.z.min - pad(bbox.z.min));
+        self.max_x[k] = round_up(bbox.x.max + pad(bbox.x.max));
+        self.max_y[k] = round_up(bbox.y.max + pad(bbox.y.max));
+        self.max_z[k] = round_up(bbox.z.max + pad(bbox.z.max));
+    }
+
+    /// 同时测试四个子节点，返回命中掩码（第 k 位对应第 k 个子节点）和各自的进入距离
+    fn hit(&self, ray: &SimdRay, t_min: f32, t_max: f32) -> (u32, Vec4) {
+        let tx0 = (self.min_x - ray.origin[0]) * ray.inv_dir[0];
+        let tx1 = (self.max_x - ray.origin[0]) * ray.inv_dir[0];
+        let ty0 = (self.min_y - ray.origin[1]) * ray.inv_dir[1];
+        let ty1 = (self.max_y - ray.origin[1]) * ray.inv_dir[1];
+        let tz0 = (self.min_z - ray.origin[2]) * ray.inv_dir[2];
+        let tz1 = (self.max_z - ray.origin[2]) * ray.inv_dir[2];
+
+        let t_near = tx0
+            .min(tx1)
+            .max(ty0.min(ty1))
+            .max(tz0.min(tz1))
+            .max(Vec4::splat(t_min));
+        let t_far = (tx0.max(tx1).min(ty0.max(ty1)).min(tz0.max(tz1)) * T_FAR_SCALE)
+            .min(Vec4::splat(t_max));
+
+        (t_near.cmple(t_far).bitmask(), t_near)
+    }
+}
+
+/// 广播到四个通道的射线参数
+struct SimdRay {
+    origin: [Vec4; 3],
+    inv_dir: [Vec4; 3],
+}
+
+impl SimdRay {
+    fn new(r: &Ray) -> Self {
+        let o = r.origin();
+        let d = r.direction();
+        Self {
+            origin: [
+                Vec4::splat(o.x() as f32),
+                Vec4::splat(o.y() as f32),
+                Vec4::splat(o.z() as f32),
+            ],
+            inv_dir: [
+                Vec4::splat((1.0 / d.x()) as f32),
+                Vec4::splat((1.0 / d.y()) as f32),
+                Vec4::splat((1.0 / d.z()) as f32),
+            ],
+        }
+    }
+}
+
+/// 转为不大于 `v` 的 f32
+fn round_down(v: f64) -> f32 {
+    let f = v as f32;
+    if (f as f64) > v { f.next_down() } else { f }
+}
+
+/// 转为不小于 `v` 的 f32
+fn round_up(v: f64) -> f32 {
+    let f = v as f32;
+    if (f as f64) < v { f.next_up() } else { f }
+}
+
+impl Hittable for WideBvh {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let ray = SimdRay::new(r);
+        let t_min = round_down(ray_t.min);
+
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+
+        // 栈中存放 (子节点, 图元数, 进入距离)，图元数为 0 表示内部节点
+        let mut stack = TraversalStack::<_, STACK_SIZE>::new((0u32, 0u32, f32::NEG_INFINITY));
+        stack.push((0, 0, f32::NEG_INFINITY));
+
+        while let Some((child, count, t_near)) = stack.pop() {
+            if t_near as f64 > closest_so_far * T_FAR_SCALE as f64 {
+                continue;
+            }
+
+            if count > 0 {
+                if self.hit_leaf(child, count, r, ray_t, &mut closest_so_far, rec) {
+                    hit_anything = true;
+                }
+                continue;
+            }
+
+            let node = &self.nodes[child as usize];
+            count_node_visit();
+            let (mask, t_near) = node.hit(&ray, t_min, round_up(closest_so_far));
+            if mask == 0 {
+                continue;
+            }
+
+            // 按进入距离从远到近压栈，使最近的子节点最先弹出
+            let t_near = t_near.to_array();
+            let mut hits = [(0usize, 0f32); WIDTH];
+            let mut hit_count = 0;
+            for (k, &t) in t_near.iter().enumerate() {
+                if mask & (1 << k) != 0 && node.children[k] != EMPTY_SLOT {
+                    hits[hit_count] = (k, t);
+                    hit_count += 1;
+                }
+            }
+            hits[..hit_count].sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+
+            for &(k, t) in &hits[..hit_count] {
+                stack.push((node.children[k], node.counts[k], t));
+            }
+        }
+
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    /// 按叶节点顺序哈希图元，树的结构与构建统计不影响渲染结果
+    fn hash_content(&self, hasher: &mut SceneHasher) {
+        hasher.write_tag("bvh");
+        hasher.write_u64(self.objects.len() as u64);
+        for object in &self.objects {
+            object.hash_content(hasher);
+        }
+    }
+
+    fn refitted(&self, time: Interval) -> Option<Arc<dyn Hittable + Send + Sync>> {
+        let mut bvh = self.clone();
+        bvh.refit(time);
+        Some(Arc::new(bvh))
+    }
+
+    fn bvh_stats(&self) -> Option<BvhStats> {
+        Some(self.stats)
+    }
+}